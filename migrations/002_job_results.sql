@@ -0,0 +1,13 @@
+CREATE TABLE if not exists job_results
+(
+    id          uuid primary key      default uuid_generate_v4(),
+    message_id  uuid         not null references chat_messages (id) on delete cascade,
+    worker_id   VARCHAR(256) not null,
+    started_at  TIMESTAMPTZ  NOT NULL DEFAULT now(),
+    finished_at TIMESTAMPTZ,
+    duration_ms BIGINT,
+    result      jsonb,
+    error       text
+);
+
+CREATE INDEX if not exists job_results_message_id_idx ON job_results (message_id);
@@ -0,0 +1,13 @@
+ALTER TABLE chat_messages
+    ADD COLUMN if not exists priority INT NOT NULL DEFAULT 0;
+
+CREATE TABLE if not exists queue_state
+(
+    id         INT primary key       default 1 CHECK (id = 1),
+    paused     bool        NOT NULL  default false,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+INSERT INTO queue_state (id)
+VALUES (1)
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,17 @@
+ALTER TABLE chat_messages
+    ADD COLUMN if not exists run_at TIMESTAMPTZ NOT NULL DEFAULT now();
+
+CREATE INDEX if not exists chat_messages_status_run_at_idx ON chat_messages (status, run_at);
+
+CREATE TABLE if not exists schedules
+(
+    id          uuid primary key      default uuid_generate_v4(),
+    name        TEXT         NOT NULL UNIQUE,
+    cron        TEXT         NOT NULL,
+    username    VARCHAR(256) NOT NULL default 'admin',
+    message     TEXT         NOT NULL,
+    enabled     bool         NOT NULL default true,
+    last_run_at TIMESTAMPTZ,
+    next_run_at TIMESTAMPTZ  NOT NULL,
+    created_at  TIMESTAMPTZ  NOT NULL DEFAULT now()
+);
@@ -0,0 +1,4 @@
+ALTER TABLE chat_messages
+    ADD COLUMN if not exists rejection_reason text;
+
+CREATE INDEX if not exists chat_messages_status_created_at_idx ON chat_messages (status, created_at);
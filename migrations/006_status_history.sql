@@ -0,0 +1,35 @@
+CREATE TABLE if not exists chat_message_history
+(
+    id         BIGSERIAL primary key,
+    message_id uuid         not null references chat_messages (id) on delete cascade,
+    old_status VARCHAR(100),
+    new_status VARCHAR(100) not null,
+    actor      VARCHAR(256) not null,
+    reason     text,
+    created_at TIMESTAMPTZ  NOT NULL DEFAULT now()
+);
+
+CREATE INDEX if not exists chat_message_history_message_id_idx ON chat_message_history (message_id, created_at);
+
+-- Actor and reason come from transaction local settings, see jobs::history::begin_as
+CREATE OR REPLACE FUNCTION record_status_change()
+    RETURNS TRIGGER AS
+$$
+BEGIN
+    IF TG_OP = 'INSERT' OR NEW.status IS DISTINCT FROM OLD.status THEN
+        INSERT INTO chat_message_history (message_id, old_status, new_status, actor, reason)
+        VALUES (NEW.id,
+                CASE WHEN TG_OP = 'UPDATE' THEN OLD.status END,
+                NEW.status,
+                COALESCE(NULLIF(current_setting('app.actor', true), ''), 'system'),
+                NULLIF(current_setting('app.reason', true), ''));
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE TRIGGER record_status_change
+    AFTER INSERT OR UPDATE OF status
+    ON chat_messages
+    FOR EACH ROW
+EXECUTE FUNCTION record_status_change();
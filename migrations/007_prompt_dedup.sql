@@ -0,0 +1,9 @@
+ALTER TABLE chat_messages
+    ADD COLUMN if not exists normalized_text text,
+    ADD COLUMN if not exists normalized_hash VARCHAR(64);
+
+CREATE INDEX if not exists chat_messages_normalized_hash_idx ON chat_messages (normalized_hash, created_at);
+
+-- The same prompt can't wait in the queue twice, finished ones are checked against a time window
+CREATE UNIQUE INDEX if not exists chat_messages_active_normalized_hash_idx ON chat_messages (normalized_hash)
+    WHERE status IN ('UNVERIFIED', 'AWAITING', 'IN_PROCESS');
@@ -0,0 +1,11 @@
+CREATE TABLE if not exists chat_notifications
+(
+    id         BIGSERIAL primary key,
+    message_id uuid references chat_messages (id) on delete cascade,
+    username   VARCHAR(256) NOT NULL,
+    text       text         NOT NULL,
+    sent_at    TIMESTAMPTZ,
+    created_at TIMESTAMPTZ  NOT NULL DEFAULT now()
+);
+
+CREATE INDEX if not exists chat_notifications_pending_idx ON chat_notifications (created_at) WHERE sent_at IS NULL;
@@ -0,0 +1,10 @@
+CREATE TABLE if not exists job_output
+(
+    id         bigserial primary key,
+    message_id uuid        not null references chat_messages (id) on delete cascade,
+    stream     VARCHAR(16) not null,
+    line       text        not null,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX if not exists job_output_message_id_idx ON job_output (message_id, id);
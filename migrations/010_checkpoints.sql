@@ -0,0 +1,11 @@
+CREATE TABLE if not exists job_checkpoints
+(
+    message_id  uuid primary key references chat_messages (id) on delete cascade,
+    workspace   text        not null,
+    base_sha    VARCHAR(64) not null,
+    head_sha    VARCHAR(64),
+    diff        text,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    finished_at TIMESTAMPTZ,
+    reverted_at TIMESTAMPTZ
+);
@@ -0,0 +1,2 @@
+ALTER TABLE job_checkpoints
+    ADD COLUMN if not exists promoted_at TIMESTAMPTZ;
@@ -0,0 +1,3 @@
+ALTER TABLE chat_messages
+    ADD COLUMN if not exists screening          jsonb,
+    ADD COLUMN if not exists screening_override BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,11 @@
+CREATE TABLE if not exists prompt_templates
+(
+    name       VARCHAR(100) primary key,
+    body       text         not null,
+    -- stored command, e.g. !STORE, NULL matches every command
+    command    VARCHAR(100),
+    -- agent backend, e.g. tmux, NULL matches every backend
+    backend    VARCHAR(100),
+    active     BOOLEAN      NOT NULL DEFAULT false,
+    updated_at TIMESTAMPTZ  NOT NULL DEFAULT now()
+);
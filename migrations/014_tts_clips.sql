@@ -0,0 +1,11 @@
+CREATE TABLE if not exists tts_clips
+(
+    message_id UUID primary key references chat_messages (id) on delete cascade,
+    -- wav or ogg, the file is <TTS_AUDIO_DIR>/<message_id>.<format>
+    format     VARCHAR(10) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    -- set once the overlay fetched the clip, or when it was skipped
+    played_at  TIMESTAMPTZ
+);
+
+CREATE INDEX if not exists tts_clips_pending_idx ON tts_clips (created_at) WHERE played_at IS NULL;
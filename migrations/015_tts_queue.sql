@@ -0,0 +1,26 @@
+CREATE TABLE if not exists tts_queue
+(
+    id          BIGSERIAL primary key,
+    message_id  uuid        NOT NULL references chat_messages (id) on delete cascade,
+    command     VARCHAR(100) NOT NULL,
+    sender      VARCHAR(255) NOT NULL,
+    text        text        NOT NULL,
+    -- PENDING, SPEAKING, DONE, SKIPPED or FAILED
+    status      VARCHAR(20) NOT NULL DEFAULT 'PENDING',
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    finished_at TIMESTAMPTZ
+);
+
+CREATE INDEX if not exists tts_queue_pending_idx ON tts_queue (id) WHERE status = 'PENDING';
+
+CREATE TABLE if not exists tts_state
+(
+    id         INT primary key      default 1 CHECK (id = 1),
+    -- percent, 100 is the natural loudness of the engine
+    volume     INT         NOT NULL default 100 CHECK (volume BETWEEN 0 AND 100),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+INSERT INTO tts_state (id)
+VALUES (1)
+ON CONFLICT DO NOTHING;
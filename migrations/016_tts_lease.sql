@@ -0,0 +1,8 @@
+-- a SPEAKING row belongs to a worker for a limited time, so a crash can't block the queue
+ALTER TABLE tts_queue
+    ADD COLUMN if not exists worker_id  VARCHAR(255),
+    ADD COLUMN if not exists started_at TIMESTAMPTZ;
+
+-- bumped on every skip, the overlay stops the clip it is playing when the value changes
+ALTER TABLE tts_state
+    ADD COLUMN if not exists skips INT NOT NULL DEFAULT 0;
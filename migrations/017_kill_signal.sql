@@ -0,0 +1,3 @@
+-- set by the kill switch, every worker reverts its local state (agent pane, speaker, theme) when it changes
+ALTER TABLE queue_state
+    ADD COLUMN if not exists killed_at TIMESTAMPTZ;
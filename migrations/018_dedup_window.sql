@@ -0,0 +1,3 @@
+-- near duplicate detection scans the prompts of the dedup window, newest first
+CREATE INDEX if not exists chat_messages_normalized_created_at_idx ON chat_messages (created_at)
+    WHERE normalized_text IS NOT NULL;
@@ -0,0 +1,147 @@
+use crate::agent::{AgentBackend, AgentJob, AgentOutcome};
+use crate::jobs::output::{OutputLine, OutputStream};
+use crate::redact::redact;
+use crate::workspace::sandbox::{self, SandboxMode};
+use crate::workspace::workspace_dir;
+use anyhow::anyhow;
+use async_trait::async_trait;
+use std::env;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, Command};
+use tokio::time::timeout;
+use tracing::{error, info};
+
+/// Runs the agent CLI as a child process per prompt, e.g. `AGENT_COMMAND="gemini --yolo -p"`.
+/// The prompt is passed as the last argument, or written to stdin with `AGENT_PROMPT_VIA=stdin`.
+pub struct HeadlessBackend {
+    program: String,
+    args: Vec<String>,
+    prompt_via_stdin: bool,
+    job_id: String,
+    child: Option<Child>,
+    output: String,
+}
+
+impl HeadlessBackend {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let command = env::var("AGENT_COMMAND").unwrap_or("gemini -p".to_string());
+        let mut parts = command.split_whitespace().map(String::from);
+        let program = parts.next().ok_or(anyhow!("AGENT_COMMAND is empty"))?;
+        let prompt_via_stdin = env::var("AGENT_PROMPT_VIA").is_ok_and(|v| v == "stdin");
+        Ok(Self {
+            program,
+            args: parts.collect(),
+            prompt_via_stdin,
+            job_id: String::new(),
+            child: None,
+            output: String::new(),
+        })
+    }
+
+    async fn record_line(&mut self, stream: OutputStream, line: &str) {
+        // the worker log is often visible on stream too
+        let line = redact(line, "agent output");
+        info!("[{stream}] {line}");
+        self.output.push_str(&line);
+        self.output.push('\n');
+        if let Err(err) = OutputLine::append(&self.job_id, stream, &line).await {
+            error!("Error storing agent output {:?}", err);
+        }
+    }
+
+    /// Streams stdout and stderr line by line into `job_output` until the process exits
+    async fn stream_until_exit(&mut self) -> anyhow::Result<AgentOutcome> {
+        let child = self.child.as_mut().ok_or(anyhow!("No prompt submitted"))?;
+        let stdout = child.stdout.take().ok_or(anyhow!("Stdout is not piped"))?;
+        let stderr = child.stderr.take().ok_or(anyhow!("Stderr is not piped"))?;
+        let mut stdout = BufReader::new(stdout).lines();
+        let mut stderr = BufReader::new(stderr).lines();
+        let (mut stdout_open, mut stderr_open) = (true, true);
+        while stdout_open || stderr_open {
+            tokio::select! {
+                line = stdout.next_line(), if stdout_open => match line? {
+                    Some(line) => self.record_line(OutputStream::Stdout, &line).await,
+                    None => stdout_open = false,
+                },
+                line = stderr.next_line(), if stderr_open => match line? {
+                    Some(line) => self.record_line(OutputStream::Stderr, &line).await,
+                    None => stderr_open = false,
+                },
+            }
+        }
+        let mut child = self.child.take().ok_or(anyhow!("No prompt submitted"))?;
+        let status = child.wait().await?;
+        Ok(AgentOutcome {
+            exit_code: status.code(),
+        })
+    }
+}
+
+#[async_trait]
+impl AgentBackend for HeadlessBackend {
+    fn name(&self) -> &'static str {
+        "headless"
+    }
+
+    async fn submit_prompt(&mut self, job: &AgentJob) -> anyhow::Result<()> {
+        self.output.clear();
+        self.job_id = job.id.clone();
+        let (program, args) = sandbox::wrap_command(&self.program, &self.args, false)?;
+        let mut command = Command::new(program);
+        if let Some(dir) = workspace_dir() {
+            command.current_dir(dir);
+        }
+        if SandboxMode::from_env() != SandboxMode::Off {
+            command.env_clear().envs(sandbox::agent_env());
+        }
+        command
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if self.prompt_via_stdin {
+            command.stdin(Stdio::piped());
+        } else {
+            command.arg(&job.prompt).stdin(Stdio::null());
+        }
+        let mut child = command.spawn()?;
+        if self.prompt_via_stdin {
+            let mut stdin = child.stdin.take().ok_or(anyhow!("Stdin is not piped"))?;
+            stdin.write_all(job.prompt.as_bytes()).await?;
+            // closing stdin tells the agent the prompt is complete
+            drop(stdin);
+        }
+        self.child = Some(child);
+        Ok(())
+    }
+
+    /// Kills the agent when it runs longer than `AGENT_TIMEOUT_SECS`, default 15 minutes
+    async fn wait_for_completion(&mut self) -> anyhow::Result<AgentOutcome> {
+        let secs = env::var("AGENT_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(900);
+        match timeout(Duration::from_secs(secs), self.stream_until_exit()).await {
+            Ok(outcome) => outcome,
+            Err(_) => {
+                self.cancel().await?;
+                Err(anyhow!(
+                    "Agent did not finish within {secs}s and was killed"
+                ))
+            }
+        }
+    }
+
+    async fn cancel(&mut self) -> anyhow::Result<()> {
+        if let Some(mut child) = self.child.take() {
+            child.kill().await?;
+        }
+        Ok(())
+    }
+
+    async fn fetch_output(&mut self) -> anyhow::Result<String> {
+        Ok(self.output.clone())
+    }
+}
@@ -0,0 +1,116 @@
+pub mod headless;
+pub mod scripted;
+pub mod tmux;
+
+use crate::agent::headless::HeadlessBackend;
+use crate::agent::scripted::ScriptedBackend;
+use crate::agent::tmux::TmuxBackend;
+use anyhow::anyhow;
+use async_trait::async_trait;
+use std::env;
+
+/// Prompt handed over to the agent
+#[derive(Debug, Clone)]
+pub struct AgentJob {
+    pub id: String,
+    pub username: String,
+    pub prompt: String,
+}
+
+#[derive(Debug)]
+pub struct AgentOutcome {
+    /// Only known for backends owning the agent process
+    pub exit_code: Option<i32>,
+}
+
+/// Drives the coding agent a `!PROMPT` is executed by.
+/// One backend instance lives for the whole worker and handles one job at a time.
+#[async_trait]
+pub trait AgentBackend: Send {
+    fn name(&self) -> &'static str;
+
+    async fn submit_prompt(&mut self, job: &AgentJob) -> anyhow::Result<()>;
+
+    /// Resolves once the agent is done with the submitted prompt
+    async fn wait_for_completion(&mut self) -> anyhow::Result<AgentOutcome>;
+
+    /// Stops the current prompt, leaving the backend ready for the next one
+    async fn cancel(&mut self) -> anyhow::Result<()>;
+
+    /// Output the agent produced for the current prompt so far
+    async fn fetch_output(&mut self) -> anyhow::Result<String>;
+}
+
+/// Submits the prompt, waits for the agent and returns the outcome with the output it produced
+pub async fn run_prompt(
+    agent: &mut dyn AgentBackend,
+    job: &AgentJob,
+) -> anyhow::Result<(AgentOutcome, String)> {
+    agent
+        .submit_prompt(job)
+        .await
+        .map_err(|e| anyhow!("{} agent error {e:?}", agent.name()))?;
+    let outcome = agent.wait_for_completion().await?;
+    let output = agent.fetch_output().await?;
+    Ok((outcome, output))
+}
+
+/// Picks the backend from `AGENT_BACKEND`: `tmux` (default), `headless` or `scripted`
+pub fn backend_from_env() -> anyhow::Result<Box<dyn AgentBackend>> {
+    let backend = env::var("AGENT_BACKEND").unwrap_or("tmux".to_string());
+    let backend: Box<dyn AgentBackend> = match backend.as_str() {
+        "tmux" => Box::new(TmuxBackend::from_env()?),
+        "headless" => Box::new(HeadlessBackend::from_env()?),
+        "scripted" => Box::new(ScriptedBackend::from_env()),
+        other => return Err(anyhow!("Unknown AGENT_BACKEND {other}")),
+    };
+    Ok(backend)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn job(prompt: &str) -> AgentJob {
+        AgentJob {
+            id: "job".to_string(),
+            username: "viewer".to_string(),
+            prompt: prompt.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn runs_prompt_through_scripted_backend() {
+        let mut agent = ScriptedBackend::new("Done: {prompt}", Duration::ZERO, 0);
+        let (outcome, output) = run_prompt(&mut agent, &job("make it pink")).await.unwrap();
+        assert_eq!(outcome.exit_code, Some(0));
+        assert_eq!(output, "Done: make it pink");
+    }
+
+    #[tokio::test]
+    async fn each_prompt_gets_its_own_output() {
+        let mut agent = ScriptedBackend::new("{prompt}", Duration::ZERO, 0);
+        run_prompt(&mut agent, &job("first")).await.unwrap();
+        let (_, output) = run_prompt(&mut agent, &job("second")).await.unwrap();
+        assert_eq!(output, "second");
+    }
+
+    #[tokio::test]
+    async fn reports_failing_exit_code() {
+        let mut agent = ScriptedBackend::new("boom", Duration::ZERO, 2);
+        let (outcome, output) = run_prompt(&mut agent, &job("x")).await.unwrap();
+        assert_eq!(outcome.exit_code, Some(2));
+        assert_eq!(output, "boom");
+    }
+
+    #[tokio::test]
+    async fn cancel_replaces_output() {
+        let mut agent = ScriptedBackend::new("{prompt}", Duration::from_secs(60), 0);
+        agent.submit_prompt(&job("slow")).await.unwrap();
+        let wait = tokio::time::timeout(Duration::from_millis(10), agent.wait_for_completion());
+        assert!(wait.await.is_err());
+        agent.cancel().await.unwrap();
+        assert_eq!(agent.fetch_output().await.unwrap(), "cancelled");
+    }
+}
@@ -0,0 +1,79 @@
+use crate::agent::{AgentBackend, AgentJob, AgentOutcome};
+use async_trait::async_trait;
+use std::env;
+use std::time::Duration;
+use tracing::info;
+
+/// Fake agent for dry runs and tests, answers every prompt with canned output.
+/// `SCRIPTED_AGENT_OUTPUT` may contain `{prompt}`, `SCRIPTED_AGENT_DELAY_MS` and
+/// `SCRIPTED_AGENT_EXIT_CODE` shape the rest of the run.
+pub struct ScriptedBackend {
+    output_template: String,
+    delay: Duration,
+    exit_code: i32,
+    submitted: Option<AgentJob>,
+    output: String,
+}
+
+impl ScriptedBackend {
+    pub fn new(output_template: &str, delay: Duration, exit_code: i32) -> Self {
+        Self {
+            output_template: output_template.to_string(),
+            delay,
+            exit_code,
+            submitted: None,
+            output: String::new(),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let output = env::var("SCRIPTED_AGENT_OUTPUT").unwrap_or("Done: {prompt}".to_string());
+        let delay = env::var("SCRIPTED_AGENT_DELAY_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(1000);
+        let exit_code = env::var("SCRIPTED_AGENT_EXIT_CODE")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0);
+        Self::new(&output, Duration::from_millis(delay), exit_code)
+    }
+}
+
+#[async_trait]
+impl AgentBackend for ScriptedBackend {
+    fn name(&self) -> &'static str {
+        "scripted"
+    }
+
+    async fn submit_prompt(&mut self, job: &AgentJob) -> anyhow::Result<()> {
+        info!(
+            "Scripted agent got prompt {:?} from {} for job {}",
+            job.prompt, job.username, job.id
+        );
+        self.output.clear();
+        self.submitted = Some(job.clone());
+        Ok(())
+    }
+
+    async fn wait_for_completion(&mut self) -> anyhow::Result<AgentOutcome> {
+        tokio::time::sleep(self.delay).await;
+        let prompt = self
+            .submitted
+            .as_ref()
+            .map_or("", |job| job.prompt.as_str());
+        self.output = self.output_template.replace("{prompt}", prompt);
+        Ok(AgentOutcome {
+            exit_code: Some(self.exit_code),
+        })
+    }
+
+    async fn cancel(&mut self) -> anyhow::Result<()> {
+        self.output = "cancelled".to_string();
+        Ok(())
+    }
+
+    async fn fetch_output(&mut self) -> anyhow::Result<String> {
+        Ok(self.output.clone())
+    }
+}
@@ -0,0 +1,258 @@
+use crate::agent::{AgentBackend, AgentJob, AgentOutcome};
+use crate::jobs::output::{OutputLine, OutputStream};
+use crate::terminal::completion::{ApprovalPolicy, CompletionDetector, PaneState};
+use crate::terminal::session::ensure_agent_pane;
+use crate::terminal::{
+    capture_pane, reset_pane, send_enter, send_esc, send_keys, send_keystrokes_without_enter,
+    wait_for_manual_exit,
+};
+use async_trait::async_trait;
+use std::env;
+use std::time::{Duration, Instant};
+use tokio::time::interval;
+use tracing::{error, info};
+
+/// Types the prompt into the interactive agent running in the tmux pane.
+/// While the agent works the pane is captured every `PANE_CAPTURE_SECS` and new lines are
+/// published as job output, the same captures tell when the agent is done.
+pub struct TmuxBackend {
+    detector: CompletionDetector,
+    job_id: String,
+    snapshot: Vec<String>,
+    transcript: Vec<String>,
+}
+
+impl TmuxBackend {
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(Self {
+            detector: CompletionDetector::from_env()?,
+            job_id: String::new(),
+            snapshot: Vec::new(),
+            transcript: Vec::new(),
+        })
+    }
+
+    /// Publishes new pane lines and returns the full capture
+    async fn capture(&mut self) -> anyhow::Result<String> {
+        let pane = capture_pane().await?;
+        let lines: Vec<String> = pane.lines().map(|l| l.trim_end().to_string()).collect();
+        for line in new_lines(&self.snapshot, &lines) {
+            if let Err(err) = OutputLine::append(&self.job_id, OutputStream::Pane, line).await {
+                error!("Error storing pane output {:?}", err);
+            }
+            self.transcript.push(line.to_string());
+        }
+        self.snapshot = lines;
+        Ok(pane)
+    }
+
+    async fn answer_approval(&self) -> anyhow::Result<()> {
+        let keys = match self.detector.policy {
+            ApprovalPolicy::Approve => &self.detector.approve_keys,
+            ApprovalPolicy::Deny => &self.detector.deny_keys,
+            ApprovalPolicy::Manual => return Ok(()),
+        };
+        info!("Answering agent confirmation with {:?}", keys);
+        send_keys(keys).await?;
+        Ok(())
+    }
+}
+
+/// Lines of `next` that were not in `prev`. The captures are aligned by how far the scrollback
+/// moved, so lines dropping off the top at `history-limit` and spinners redrawn above the bottom
+/// don't republish the rest. Changed lines above the old end are redraws and are left out, as are
+/// trailing blank lines the agent is likely still drawing into.
+fn new_lines<'a>(prev: &[String], next: &'a [String]) -> &'a [String] {
+    let end = content_end(next);
+    let prev_end = content_end(prev);
+    let start = match scroll_offset(&prev[..prev_end], next) {
+        Some(offset) => prev_end - offset,
+        // nothing in common, e.g. the screen was cleared
+        None => 0,
+    };
+    if end <= start { &[] } else { &next[start..end] }
+}
+
+/// Index after the last non-blank line
+fn content_end(lines: &[String]) -> usize {
+    lines
+        .iter()
+        .rposition(|line| !line.is_empty())
+        .map_or(0, |i| i + 1)
+}
+
+/// How many lines `prev` scrolled up to become `next`: `prev[i + offset]` is `next[i]`.
+/// Candidates come from where the last lines of `prev` show up in `next`, the one lining up
+/// the most non-blank lines wins, ties go to the smaller offset.
+fn scroll_offset(prev: &[String], next: &[String]) -> Option<usize> {
+    let mut candidates = vec![0];
+    for (anchor, line) in prev.iter().enumerate().rev().take(5) {
+        for (position, candidate) in next.iter().enumerate() {
+            if candidate == line && !line.is_empty() && anchor >= position {
+                candidates.push(anchor - position);
+            }
+        }
+    }
+    candidates.sort_unstable();
+    candidates.dedup();
+    let score = |offset: usize| {
+        prev.iter()
+            .skip(offset)
+            .zip(next)
+            .filter(|(a, b)| !a.is_empty() && a == b)
+            .count()
+    };
+    candidates
+        .into_iter()
+        .map(|offset| (score(offset), offset))
+        .filter(|(score, _)| *score > 0)
+        .max_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)))
+        .map(|(_, offset)| offset)
+}
+
+#[async_trait]
+impl AgentBackend for TmuxBackend {
+    fn name(&self) -> &'static str {
+        "tmux"
+    }
+
+    async fn submit_prompt(&mut self, job: &AgentJob) -> anyhow::Result<()> {
+        ensure_agent_pane().await?;
+        self.job_id = job.id.clone();
+        self.transcript.clear();
+        // only what shows up after the prompt belongs to this job
+        let pane = capture_pane().await.unwrap_or_default();
+        self.snapshot = pane.lines().map(|l| l.trim_end().to_string()).collect();
+
+        info!("Pressing ESC to clear any previous input");
+        send_esc().await?;
+
+        info!("Typing prompt");
+        send_keystrokes_without_enter(&job.prompt).await?;
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        send_enter().await?;
+        let pane = capture_pane().await.unwrap_or(pane);
+        self.detector.reset(&pane, &job.prompt);
+        Ok(())
+    }
+
+    /// Waits until the agent looks idle, answering confirmations on the way.
+    /// Typing `/` in the bot terminal still ends the job manually.
+    async fn wait_for_completion(&mut self) -> anyhow::Result<AgentOutcome> {
+        info!("Waiting for the agent, type `/` at any time to finish the job");
+        let secs = env::var("PANE_CAPTURE_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(2);
+        let mut ticker = interval(Duration::from_secs(secs));
+        let manual = wait_for_manual_exit();
+        tokio::pin!(manual);
+        loop {
+            tokio::select! {
+                _ = &mut manual => break,
+                _ = ticker.tick() => {
+                    let pane = match self.capture().await {
+                        Ok(pane) => pane,
+                        Err(err) => {
+                            error!("Error capturing pane {:?}", err);
+                            continue;
+                        }
+                    };
+                    match self.detector.observe(&pane, Instant::now()) {
+                        PaneState::Working => {}
+                        PaneState::Idle => break,
+                        PaneState::Approval => self.answer_approval().await?,
+                    }
+                }
+            }
+        }
+        self.capture().await?;
+        info!("Exiting terminal task");
+        Ok(AgentOutcome { exit_code: None })
+    }
+
+    async fn cancel(&mut self) -> anyhow::Result<()> {
+        reset_pane().await?;
+        Ok(())
+    }
+
+    /// Everything captured from the pane since the prompt was submitted
+    async fn fetch_output(&mut self) -> anyhow::Result<String> {
+        Ok(self.transcript.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &[&str]) -> Vec<String> {
+        text.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn publishes_appended_lines() {
+        let prev = lines(&["$ gemini", "> prompt", ""]);
+        let next = lines(&["$ gemini", "> prompt", "reading files", "done", ""]);
+        assert_eq!(new_lines(&prev, &next), lines(&["reading files", "done"]));
+    }
+
+    #[test]
+    fn publishes_lines_drawn_into_blank_screen() {
+        let prev = lines(&["> prompt", "", "", ""]);
+        let next = lines(&["> prompt", "step 1", "", ""]);
+        assert_eq!(new_lines(&prev, &next), lines(&["step 1"]));
+    }
+
+    #[test]
+    fn nothing_new_without_changes() {
+        let prev = lines(&["a", "b", ""]);
+        assert!(new_lines(&prev, &prev).is_empty());
+    }
+
+    #[test]
+    fn handles_lines_scrolling_off_the_top() {
+        let prev = lines(&["one", "two", "three", "four"]);
+        let next = lines(&["three", "four", "five", "six"]);
+        assert_eq!(new_lines(&prev, &next), lines(&["five", "six"]));
+    }
+
+    #[test]
+    fn ignores_spinner_redrawn_above_the_bottom() {
+        let prev = lines(&[
+            "> prompt",
+            "output 1",
+            "⠋ Thinking (esc to cancel)",
+            "status bar",
+        ]);
+        let next = lines(&[
+            "> prompt",
+            "output 1",
+            "⠙ Thinking (esc to cancel)",
+            "status bar",
+            "output 2",
+        ]);
+        assert_eq!(new_lines(&prev, &next), lines(&["output 2"]));
+    }
+
+    #[test]
+    fn handles_spinner_while_scrolling_off() {
+        let prev = lines(&["old", "a", "b", "⠋ working", "footer"]);
+        let next = lines(&["a", "b", "⠙ working", "footer", "c"]);
+        assert_eq!(new_lines(&prev, &next), lines(&["c"]));
+    }
+
+    #[test]
+    fn publishes_everything_after_a_clear() {
+        let prev = lines(&["a", "b"]);
+        let next = lines(&["x", "y"]);
+        assert_eq!(new_lines(&prev, &next), lines(&["x", "y"]));
+    }
+
+    #[test]
+    fn publishes_repeated_lines() {
+        let prev = lines(&["> prompt", "ok"]);
+        let next = lines(&["> prompt", "ok", "ok"]);
+        assert_eq!(new_lines(&prev, &next), lines(&["ok"]));
+    }
+}
@@ -0,0 +1,22 @@
+use crate::tts::clip::AudioClip;
+use actix_web::error::{ErrorBadRequest, ErrorInternalServerError, ErrorNotFound};
+use actix_web::{HttpResponse, get, web};
+
+/// Rendered announcement of a job, public like `/config` so the browser source can play it
+#[get("/audio/{id}")]
+async fn get_audio(path: web::Path<String>) -> actix_web::Result<HttpResponse> {
+    let clip = AudioClip::get(&path.into_inner())
+        .await
+        .map_err(ErrorBadRequest)?
+        .ok_or(ErrorNotFound("No audio for this job"))?;
+    let audio = tokio::fs::read(clip.path())
+        .await
+        .map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok()
+        .content_type(clip.content_type())
+        .body(audio))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_audio);
+}
@@ -0,0 +1,17 @@
+use actix_web::HttpRequest;
+use actix_web::error::ErrorUnauthorized;
+use std::env;
+
+/// Checks `Authorization: Bearer <ADMIN_TOKEN>`, every request is rejected while the env is unset
+pub fn authorize(req: &HttpRequest) -> actix_web::Result<()> {
+    let expected = env::var("ADMIN_TOKEN").ok().filter(|t| !t.is_empty());
+    let provided = req
+        .headers()
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "));
+    match (expected, provided) {
+        (Some(expected), Some(provided)) if expected == provided => Ok(()),
+        _ => Err(ErrorUnauthorized("Invalid admin token")),
+    }
+}
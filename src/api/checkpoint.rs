@@ -0,0 +1,53 @@
+use crate::api::auth::authorize;
+use crate::jobs::history::Actor;
+use crate::workspace::Checkpoint;
+use actix_web::error::{ErrorBadRequest, ErrorNotFound};
+use actix_web::{HttpRequest, Responder, get, post, web};
+use serde::Serialize;
+use uuid::Uuid;
+
+#[derive(Serialize)]
+struct QueuedResponse {
+    /// Job whose changes are reverted or promoted once the worker picks up the request
+    job: Uuid,
+}
+
+#[get("/jobs/{id}/checkpoint")]
+async fn get_checkpoint(
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> actix_web::Result<impl Responder> {
+    authorize(&req)?;
+    let checkpoint = Checkpoint::get(&path.into_inner())
+        .await
+        .map_err(ErrorBadRequest)?
+        .ok_or(ErrorNotFound("No checkpoint for this job"))?;
+    Ok(web::Json(checkpoint))
+}
+
+#[post("/jobs/{id}/undo")]
+async fn undo_job(req: HttpRequest, path: web::Path<String>) -> actix_web::Result<impl Responder> {
+    authorize(&req)?;
+    let job = Checkpoint::request_undo(&path.into_inner(), &Actor::Admin)
+        .await
+        .map_err(ErrorBadRequest)?;
+    Ok(web::Json(QueuedResponse { job }))
+}
+
+#[post("/jobs/{id}/promote")]
+async fn promote_job(
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> actix_web::Result<impl Responder> {
+    authorize(&req)?;
+    let job = Checkpoint::request_promote(&path.into_inner(), &Actor::Admin)
+        .await
+        .map_err(ErrorBadRequest)?;
+    Ok(web::Json(QueuedResponse { job }))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_checkpoint)
+        .service(undo_job)
+        .service(promote_job);
+}
@@ -1,26 +1,35 @@
 use crate::api::website_config::WebsiteConfig;
+use crate::chaos::theme_names;
+use crate::jobs::JobResult;
+use crate::jobs::history::{Actor, StatusChange};
+use crate::jobs::output::OutputLine;
 use crate::open_ai::OpenAI;
 use crate::open_ai::types::ApiMessage;
 use crate::pg::pg::PgConnect;
+use crate::redact::redact;
+use crate::tts::clip::AudioClip;
+use crate::tts::queue::{TtsControls, TtsQueue};
 use crate::twitch::chat_message::{ChatMessage, MessageStatus};
+use actix_web::error::ErrorInternalServerError;
 use actix_web::{App, HttpServer, Responder, get, post, web};
 use serde::de::Unexpected::Str;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use std::time::Duration;
+use tokio_util::sync::CancellationToken;
 use tracing::{error, info};
+use uuid::Uuid;
 
+mod audio;
+pub mod auth;
+mod checkpoint;
+mod moderation;
+mod prompt_template;
+mod queue;
+mod schedule;
+mod tts;
 pub mod website_config;
 
-#[derive(Serialize)]
-struct ChatMessagesResponse {
-    messages: Vec<ChatMessage>,
-}
-
-#[derive(Deserialize)]
-struct BulkUpdateRequest {
-    ids: Vec<String>,
-}
-
 #[derive(Deserialize, Debug)]
 struct GetMessage {
     messages: Vec<ApiMessage>,
@@ -48,8 +57,8 @@ struct AddNonUserMessage {
 }
 
 #[derive(Serialize)]
-struct SuccessResponse {
-    success: bool,
+pub(crate) struct SuccessResponse {
+    pub(crate) success: bool,
 }
 
 #[derive(Serialize)]
@@ -66,28 +75,18 @@ struct ConfigResponse {
     erase_message: bool,
     admin_message: Option<String>,
     companion_message: Option<String>,
-}
-
-#[get("/all")]
-async fn hello() -> impl Responder {
-    let messages = ChatMessage::get_all_unverified().await.unwrap();
-    web::Json(ChatMessagesResponse { messages })
+    /// `/audio/{id}` of the next announcement the overlay should play
+    audio: Option<String>,
+    /// TTS volume in percent for the audio element
+    volume: i32,
+    /// Changes whenever TTS is skipped, the overlay stops the clip it is playing
+    tts_skips: i32,
 }
 
 #[get("/paths")]
-async fn paths() -> impl Responder {
-    let dir_path = "/Users/nikitapoznyakov/Desktop/chat-tvari";
-    let mut paths = Vec::new();
-    let mut entries = tokio::fs::read_dir(dir_path).await.unwrap();
-    while let Some(entry) = entries.next_entry().await.unwrap() {
-        let path = entry.path();
-        if path.is_file() {
-            if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-                paths.push(name.to_string().replace(".css", ""));
-            }
-        }
-    }
-    web::Json(paths)
+async fn paths() -> actix_web::Result<impl Responder> {
+    let names = theme_names().map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(web::Json(names))
 }
 
 #[post("/update-config")]
@@ -162,16 +161,19 @@ async fn get_message(mut req: web::Json<GetMessage>) -> impl Responder {
         if rand >= 50 {
             let open_ai = OpenAI::new().unwrap();
             let response = open_ai.send_user_message(&mut req.messages).await.unwrap();
-            return web::Json(ChatResponse { response });
+            return web::Json(ChatResponse {
+                response: redact(&response, "ai reply"),
+            });
         } else {
             match ChatMessage::get_user_chat_message().await {
                 Ok(message) => {
                     message
-                        .update_status(MessageStatus::Completed)
+                        .update_status(MessageStatus::Completed, &Actor::Api, None)
                         .await
                         .unwrap();
+                    record_overlay_result(&message, "reply").await;
                     return web::Json(ChatResponse {
-                        response: message.text,
+                        response: redact(&message.text, "chat reply"),
                     });
                 }
                 Err(e) => {
@@ -184,6 +186,56 @@ async fn get_message(mut req: web::Json<GetMessage>) -> impl Responder {
     }
 }
 
+async fn record_overlay_result(message: &ChatMessage, kind: &str) {
+    let id = message.id.as_deref().unwrap_or_default();
+    let result = json!({ kind: message.text });
+    if let Err(e) = JobResult::record(id, "api", &result).await {
+        error!("Error recording job result {e:?}");
+    }
+}
+
+#[get("/jobs/results")]
+async fn get_latest_job_results() -> actix_web::Result<impl Responder> {
+    let results = JobResult::get_latest(50)
+        .await
+        .map_err(ErrorInternalServerError)?;
+    Ok(web::Json(results))
+}
+
+/// A malformed id is rejected with 400 by the `Uuid` extractor
+#[get("/jobs/{id}/results")]
+async fn get_job_results(path: web::Path<Uuid>) -> actix_web::Result<impl Responder> {
+    let results = JobResult::get_by_message(&path.into_inner().to_string())
+        .await
+        .map_err(ErrorInternalServerError)?;
+    Ok(web::Json(results))
+}
+
+#[get("/jobs/{id}/history")]
+async fn get_job_history(path: web::Path<Uuid>) -> actix_web::Result<impl Responder> {
+    let timeline = StatusChange::timeline(&path.into_inner().to_string())
+        .await
+        .map_err(ErrorInternalServerError)?;
+    Ok(web::Json(timeline))
+}
+
+#[derive(Deserialize)]
+struct OutputQuery {
+    after: Option<i64>,
+}
+
+#[get("/jobs/{id}/output")]
+async fn get_job_output(
+    path: web::Path<Uuid>,
+    query: web::Query<OutputQuery>,
+) -> actix_web::Result<impl Responder> {
+    let lines =
+        OutputLine::get_by_message(&path.into_inner().to_string(), query.after.unwrap_or(0))
+            .await
+            .map_err(ErrorInternalServerError)?;
+    Ok(web::Json(lines))
+}
+
 #[get("/config")]
 async fn get_config() -> impl Responder {
     let config = WebsiteConfig::get_config().await;
@@ -192,20 +244,41 @@ async fn get_config() -> impl Responder {
 
     if let Ok(found_admin_msg) = ChatMessage::get_admin_message().await {
         found_admin_msg
-            .update_status(MessageStatus::Completed)
+            .update_status(MessageStatus::Completed, &Actor::Api, None)
             .await
             .unwrap();
-        admin_message = Some(found_admin_msg.text);
+        record_overlay_result(&found_admin_msg, "admin_message").await;
+        if let Err(e) = TtsQueue::enqueue(&found_admin_msg).await {
+            error!("Error queueing admin announcement {e:?}");
+        }
+        admin_message = Some(redact(&found_admin_msg.text, "admin message"));
     };
 
     if let Ok(found_companion_msg) = ChatMessage::get_companion_message().await {
         found_companion_msg
-            .update_status(MessageStatus::Completed)
+            .update_status(MessageStatus::Completed, &Actor::Api, None)
             .await
             .unwrap();
-        companion_message = Some(found_companion_msg.text);
+        record_overlay_result(&found_companion_msg, "companion_message").await;
+        if let Err(e) = TtsQueue::enqueue(&found_companion_msg).await {
+            error!("Error queueing companion announcement {e:?}");
+        }
+        companion_message = Some(redact(&found_companion_msg.text, "companion message"));
     };
 
+    let audio = match AudioClip::take_next().await {
+        Ok(clip) => clip.map(|clip| format!("/audio/{}", clip.message_id)),
+        Err(e) => {
+            error!("Error getting next audio clip {e:?}");
+            None
+        }
+    };
+
+    let controls = TtsQueue::controls().await.unwrap_or(TtsControls {
+        volume: 100,
+        skips: 0,
+    });
+
     web::Json(ConfigResponse {
         sound: config.sound_name,
         theme: config.theme,
@@ -214,23 +287,48 @@ async fn get_config() -> impl Responder {
         erase_message: config.erase_message,
         admin_message,
         companion_message,
+        audio,
+        volume: controls.volume,
+        tts_skips: controls.skips,
     })
 }
 
-pub async fn run_server() -> std::io::Result<()> {
+pub async fn run_server(shutdown: CancellationToken) -> std::io::Result<()> {
     info!("Starting server at http://localhost:8080");
 
-    HttpServer::new(|| {
+    let server = HttpServer::new(|| {
         App::new()
             .service(get_message)
             .service(get_config)
+            .service(paths)
             .service(update_prompt)
             .service(update_config)
             .service(update_erase_messages)
             .service(add_admin_message)
             .service(add_companion_message)
+            .service(get_latest_job_results)
+            .service(get_job_results)
+            .service(get_job_history)
+            .service(get_job_output)
+            .configure(audio::configure)
+            .configure(checkpoint::configure)
+            .configure(moderation::configure)
+            .configure(prompt_template::configure)
+            .configure(queue::configure)
+            .configure(schedule::configure)
+            .configure(tts::configure)
     })
+    .disable_signals()
+    .shutdown_timeout(10)
     .bind(("127.0.0.1", 8080))?
-    .run()
-    .await
+    .run();
+
+    // Signals are handled by the shared shutdown token, the server drains in-flight requests
+    let handle = server.handle();
+    tokio::spawn(async move {
+        shutdown.cancelled().await;
+        info!("Stopping http server");
+        handle.stop(true).await;
+    });
+    server.await
 }
@@ -0,0 +1,97 @@
+use crate::api::auth::authorize;
+use crate::jobs::history::Actor;
+use crate::twitch::chat_message::ChatMessage;
+use crate::twitch::moderation::{Moderation, ModerationFilter};
+use actix_web::error::{ErrorBadRequest, ErrorInternalServerError};
+use actix_web::{HttpRequest, Responder, get, post, web};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize)]
+struct ChatMessagesResponse {
+    messages: Vec<ChatMessage>,
+}
+
+#[derive(Deserialize)]
+struct BulkUpdateRequest {
+    ids: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct RejectRequest {
+    ids: Vec<String>,
+    reason: String,
+}
+
+#[derive(Deserialize)]
+struct EditRequest {
+    id: String,
+    text: String,
+}
+
+#[derive(Serialize)]
+struct AffectedResponse {
+    affected: u64,
+}
+
+#[get("/all")]
+async fn hello(req: HttpRequest) -> actix_web::Result<impl Responder> {
+    authorize(&req)?;
+    let messages = ChatMessage::get_all_unverified()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    Ok(web::Json(ChatMessagesResponse { messages }))
+}
+
+#[get("/moderation")]
+async fn list_messages(
+    req: HttpRequest,
+    filter: web::Query<ModerationFilter>,
+) -> actix_web::Result<impl Responder> {
+    authorize(&req)?;
+    let messages = Moderation::list(&filter).await.map_err(ErrorBadRequest)?;
+    Ok(web::Json(messages))
+}
+
+#[post("/moderation/approve")]
+async fn approve(
+    req: HttpRequest,
+    body: web::Json<BulkUpdateRequest>,
+) -> actix_web::Result<impl Responder> {
+    authorize(&req)?;
+    let affected = ChatMessage::bulk_update(&body.ids, &Actor::Admin)
+        .await
+        .map_err(ErrorBadRequest)?;
+    Ok(web::Json(AffectedResponse { affected }))
+}
+
+#[post("/moderation/reject")]
+async fn reject(
+    req: HttpRequest,
+    body: web::Json<RejectRequest>,
+) -> actix_web::Result<impl Responder> {
+    authorize(&req)?;
+    let affected = Moderation::reject(&body.ids, &body.reason, &Actor::Admin)
+        .await
+        .map_err(ErrorBadRequest)?;
+    Ok(web::Json(AffectedResponse { affected }))
+}
+
+#[post("/moderation/edit")]
+async fn edit_and_approve(
+    req: HttpRequest,
+    body: web::Json<EditRequest>,
+) -> actix_web::Result<impl Responder> {
+    authorize(&req)?;
+    let affected = Moderation::edit_and_approve(&body.id, &body.text, &Actor::Admin)
+        .await
+        .map_err(ErrorBadRequest)?;
+    Ok(web::Json(AffectedResponse { affected }))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(hello)
+        .service(list_messages)
+        .service(approve)
+        .service(reject)
+        .service(edit_and_approve);
+}
@@ -0,0 +1,93 @@
+use crate::api::SuccessResponse;
+use crate::api::auth::authorize;
+use crate::prompt::{PromptTemplate, VARIABLES, render};
+use actix_web::error::{ErrorBadRequest, ErrorInternalServerError, ErrorNotFound};
+use actix_web::{HttpRequest, Responder, delete, get, post, web};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+struct Preview {
+    /// Unsaved body to try out, otherwise the stored template `name` is rendered
+    body: Option<String>,
+    name: Option<String>,
+    username: Option<String>,
+    text: Option<String>,
+}
+
+#[derive(Serialize)]
+struct PreviewResponse {
+    prompt: String,
+    variables: [&'static str; 5],
+}
+
+#[get("/prompt-templates")]
+async fn get_templates(req: HttpRequest) -> actix_web::Result<impl Responder> {
+    authorize(&req)?;
+    let templates = PromptTemplate::get_all()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    Ok(web::Json(templates))
+}
+
+#[post("/prompt-templates")]
+async fn save_template(
+    req: HttpRequest,
+    body: web::Json<PromptTemplate>,
+) -> actix_web::Result<impl Responder> {
+    authorize(&req)?;
+    if body.name.trim().is_empty() {
+        return Err(ErrorBadRequest("Template name is empty"));
+    }
+    body.upsert().await.map_err(ErrorBadRequest)?;
+    Ok(web::Json(SuccessResponse { success: true }))
+}
+
+#[delete("/prompt-templates/{name}")]
+async fn delete_template(
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> actix_web::Result<impl Responder> {
+    authorize(&req)?;
+    let deleted = PromptTemplate::delete(&path.into_inner())
+        .await
+        .map_err(ErrorInternalServerError)?;
+    Ok(web::Json(SuccessResponse {
+        success: deleted > 0,
+    }))
+}
+
+#[post("/prompt-templates/preview")]
+async fn preview_template(
+    req: HttpRequest,
+    body: web::Json<Preview>,
+) -> actix_web::Result<impl Responder> {
+    authorize(&req)?;
+    let template = match (&body.body, &body.name) {
+        (Some(template), _) => template.clone(),
+        (None, Some(name)) => {
+            PromptTemplate::get(name)
+                .await
+                .map_err(ErrorBadRequest)?
+                .ok_or(ErrorNotFound("No such template"))?
+                .body
+        }
+        (None, None) => return Err(ErrorBadRequest("Either body or name is required")),
+    };
+    let variables = PromptTemplate::variables(
+        body.username.as_deref().unwrap_or("viewer"),
+        body.text.as_deref().unwrap_or("make the background pink"),
+    )
+    .await
+    .map_err(ErrorBadRequest)?;
+    Ok(web::Json(PreviewResponse {
+        prompt: render(&template, &variables),
+        variables: VARIABLES,
+    }))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_templates)
+        .service(save_template)
+        .service(delete_template)
+        .service(preview_template);
+}
@@ -0,0 +1,109 @@
+use crate::api::SuccessResponse;
+use crate::api::auth::authorize;
+use crate::event_poller::kill_switch::KillSwitch;
+use crate::event_poller::queue_control::QueueControl;
+use crate::jobs::history::Actor;
+use actix_web::error::{ErrorBadRequest, ErrorInternalServerError};
+use actix_web::{HttpRequest, Responder, get, post, web};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+struct SetPriority {
+    id: String,
+    priority: i32,
+}
+
+#[derive(Deserialize)]
+struct Requeue {
+    id: String,
+}
+
+#[derive(Serialize)]
+struct AffectedResponse {
+    affected: u64,
+}
+
+#[get("/queue")]
+async fn queue_state(req: HttpRequest) -> actix_web::Result<impl Responder> {
+    authorize(&req)?;
+    let state = QueueControl::state()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    Ok(web::Json(state))
+}
+
+#[post("/queue/pause")]
+async fn pause_queue(req: HttpRequest) -> actix_web::Result<impl Responder> {
+    authorize(&req)?;
+    QueueControl::pause()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    Ok(web::Json(SuccessResponse { success: true }))
+}
+
+#[post("/queue/resume")]
+async fn resume_queue(req: HttpRequest) -> actix_web::Result<impl Responder> {
+    authorize(&req)?;
+    QueueControl::resume()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    Ok(web::Json(SuccessResponse { success: true }))
+}
+
+#[post("/queue/skip")]
+async fn skip_current(req: HttpRequest) -> actix_web::Result<impl Responder> {
+    authorize(&req)?;
+    let affected = QueueControl::skip_current(&Actor::Admin)
+        .await
+        .map_err(ErrorInternalServerError)?;
+    Ok(web::Json(AffectedResponse { affected }))
+}
+
+#[post("/queue/priority")]
+async fn set_priority(
+    req: HttpRequest,
+    body: web::Json<SetPriority>,
+) -> actix_web::Result<impl Responder> {
+    authorize(&req)?;
+    QueueControl::set_priority(&body.id, body.priority)
+        .await
+        .map_err(ErrorBadRequest)?;
+    Ok(web::Json(SuccessResponse { success: true }))
+}
+
+#[post("/queue/requeue")]
+async fn requeue(req: HttpRequest, body: web::Json<Requeue>) -> actix_web::Result<impl Responder> {
+    authorize(&req)?;
+    let affected = QueueControl::requeue(&body.id, &Actor::Admin)
+        .await
+        .map_err(ErrorBadRequest)?;
+    Ok(web::Json(AffectedResponse { affected }))
+}
+
+#[post("/queue/purge")]
+async fn purge_queue(req: HttpRequest) -> actix_web::Result<impl Responder> {
+    authorize(&req)?;
+    let affected = QueueControl::purge(&Actor::Admin)
+        .await
+        .map_err(ErrorInternalServerError)?;
+    Ok(web::Json(AffectedResponse { affected }))
+}
+
+/// Emergency stop, see `KillSwitch`
+#[post("/kill")]
+async fn kill(req: HttpRequest) -> actix_web::Result<impl Responder> {
+    authorize(&req)?;
+    let report = KillSwitch::trigger(&Actor::Admin).await;
+    Ok(web::Json(report))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(queue_state)
+        .service(pause_queue)
+        .service(resume_queue)
+        .service(skip_current)
+        .service(set_priority)
+        .service(requeue)
+        .service(purge_queue)
+        .service(kill);
+}
@@ -0,0 +1,102 @@
+use crate::api::SuccessResponse;
+use crate::api::auth::authorize;
+use crate::schedule::{Schedule, parse_run_at, parse_scheduled_message};
+use actix_web::error::{ErrorBadRequest, ErrorInternalServerError};
+use actix_web::{HttpRequest, Responder, delete, get, post, web};
+use serde::Deserialize;
+
+fn default_username() -> String {
+    "admin".to_string()
+}
+
+#[derive(Deserialize)]
+struct ScheduleOnce {
+    message: String,
+    run_at: String,
+    #[serde(default = "default_username")]
+    username: String,
+}
+
+#[derive(Deserialize)]
+struct CreateSchedule {
+    name: String,
+    cron: String,
+    message: String,
+    #[serde(default = "default_username")]
+    username: String,
+}
+
+#[derive(Deserialize)]
+struct SetEnabled {
+    enabled: bool,
+}
+
+#[post("/schedule")]
+async fn schedule_once(
+    req: HttpRequest,
+    body: web::Json<ScheduleOnce>,
+) -> actix_web::Result<impl Responder> {
+    authorize(&req)?;
+    let run_at = parse_run_at(&body.run_at).map_err(ErrorBadRequest)?;
+    let message =
+        parse_scheduled_message(&body.username, &body.message).map_err(ErrorBadRequest)?;
+    message
+        .enqueue(Some(run_at))
+        .await
+        .map_err(ErrorInternalServerError)?;
+    Ok(web::Json(SuccessResponse { success: true }))
+}
+
+#[get("/schedules")]
+async fn get_schedules(req: HttpRequest) -> actix_web::Result<impl Responder> {
+    authorize(&req)?;
+    let schedules = Schedule::get_all()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    Ok(web::Json(schedules))
+}
+
+#[post("/schedules")]
+async fn create_schedule(
+    req: HttpRequest,
+    body: web::Json<CreateSchedule>,
+) -> actix_web::Result<impl Responder> {
+    authorize(&req)?;
+    let schedule = Schedule::create(&body.name, &body.cron, &body.username, &body.message)
+        .await
+        .map_err(ErrorBadRequest)?;
+    Ok(web::Json(schedule))
+}
+
+#[post("/schedules/{id}/enabled")]
+async fn set_schedule_enabled(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<SetEnabled>,
+) -> actix_web::Result<impl Responder> {
+    authorize(&req)?;
+    Schedule::set_enabled(&path.into_inner(), body.enabled)
+        .await
+        .map_err(ErrorBadRequest)?;
+    Ok(web::Json(SuccessResponse { success: true }))
+}
+
+#[delete("/schedules/{id}")]
+async fn delete_schedule(
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> actix_web::Result<impl Responder> {
+    authorize(&req)?;
+    Schedule::delete(&path.into_inner())
+        .await
+        .map_err(ErrorBadRequest)?;
+    Ok(web::Json(SuccessResponse { success: true }))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(schedule_once)
+        .service(get_schedules)
+        .service(create_schedule)
+        .service(set_schedule_enabled)
+        .service(delete_schedule);
+}
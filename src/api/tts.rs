@@ -0,0 +1,49 @@
+use crate::api::auth::authorize;
+use crate::tts::queue::TtsQueue;
+use actix_web::error::ErrorInternalServerError;
+use actix_web::{HttpRequest, Responder, get, post, web};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+struct SetVolume {
+    /// Percent, clamped to 0..=100
+    volume: i32,
+}
+
+#[derive(Serialize)]
+struct AffectedResponse {
+    affected: u64,
+}
+
+#[get("/tts")]
+async fn tts_state(req: HttpRequest) -> actix_web::Result<impl Responder> {
+    authorize(&req)?;
+    let state = TtsQueue::state().await.map_err(ErrorInternalServerError)?;
+    Ok(web::Json(state))
+}
+
+#[post("/tts/skip")]
+async fn skip_tts(req: HttpRequest) -> actix_web::Result<impl Responder> {
+    authorize(&req)?;
+    let affected = TtsQueue::skip_current()
+        .await
+        .map_err(ErrorInternalServerError)?;
+    Ok(web::Json(AffectedResponse { affected }))
+}
+
+#[post("/tts/volume")]
+async fn set_volume(
+    req: HttpRequest,
+    body: web::Json<SetVolume>,
+) -> actix_web::Result<impl Responder> {
+    authorize(&req)?;
+    TtsQueue::set_volume(body.volume)
+        .await
+        .map_err(ErrorInternalServerError)?;
+    let state = TtsQueue::state().await.map_err(ErrorInternalServerError)?;
+    Ok(web::Json(state))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(tts_state).service(skip_tts).service(set_volume);
+}
@@ -28,6 +28,15 @@ impl WebsiteConfig {
         Ok(())
     }
 
+    /// Hides whatever alert the overlay shows
+    pub async fn reset_alert() -> anyhow::Result<()> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "UPDATE website_config SET alert = $1";
+        client.execute(query, &[&"none"]).await?;
+        Ok(())
+    }
+
     pub async fn update_erase_message(status: &bool) -> anyhow::Result<()> {
         let pool = PgConnect::create_pool_from_env()?;
         let client = pool.get().await?;
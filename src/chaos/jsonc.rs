@@ -0,0 +1,195 @@
+use anyhow::anyhow;
+use serde_json::Value;
+use std::ops::Range;
+
+/// Where a key sits in the top-level object of a JSONC document
+#[derive(Debug, PartialEq)]
+enum Slot {
+    /// Byte range of the current value
+    Value(Range<usize>),
+    /// Key is absent, a member goes in right after byte `at`
+    Missing { at: usize, needs_comma: bool },
+}
+
+/// Sets the top-level `key` to `value` by editing the text in place, so the comments,
+/// order and formatting of the rest of the file survive
+pub fn set_top_level(content: &str, key: &str, value: &Value) -> anyhow::Result<String> {
+    let value = serde_json::to_string(value)?;
+    let member = format!("{}: {value}", serde_json::to_string(key)?);
+    if content.trim().is_empty() {
+        return Ok(format!("{{\n    {member}\n}}\n"));
+    }
+    let parsed: Value = json5::from_str(content)?;
+    if !parsed.is_object() {
+        return Err(anyhow!("Not a JSON object"));
+    }
+    let mut edited = content.to_string();
+    match find_slot(content, key)? {
+        Slot::Value(range) => edited.replace_range(range, &value),
+        Slot::Missing { at, needs_comma } => {
+            let comma = if needs_comma { "," } else { "" };
+            edited.insert_str(at, &format!("{comma}\n    {member}"));
+        }
+    }
+    Ok(edited)
+}
+
+/// Walks the document skipping strings and comments, tracking nesting so keys of nested
+/// objects don't match
+fn find_slot(content: &str, key: &str) -> anyhow::Result<Slot> {
+    let bytes = content.as_bytes();
+    let mut i = 0;
+    let mut depth = 0;
+    // end and last byte of the previous token, trivia excluded
+    let mut last_end = 0;
+    let mut last_byte = 0;
+    let mut expect_key = false;
+    let mut matched_key = false;
+    let mut awaiting_value = false;
+    let mut value_start = None;
+    while i < bytes.len() {
+        let byte = bytes[i];
+        if byte.is_ascii_whitespace() {
+            i += 1;
+            continue;
+        }
+        if byte == b'/' && bytes.get(i + 1) == Some(&b'/') {
+            i = content[i..].find('\n').map_or(bytes.len(), |n| i + n);
+            continue;
+        }
+        if byte == b'/' && bytes.get(i + 1) == Some(&b'*') {
+            i = content[i + 2..]
+                .find("*/")
+                .map_or(bytes.len(), |n| i + 2 + n + 2);
+            continue;
+        }
+
+        let start = i;
+        i = if byte == b'"' {
+            string_end(bytes, i)?
+        } else {
+            i + 1
+        };
+        if awaiting_value {
+            awaiting_value = false;
+            value_start = Some(start);
+        }
+        if depth == 1 {
+            match (byte, value_start) {
+                (b',' | b'}', Some(value_start)) => return Ok(Slot::Value(value_start..last_end)),
+                (b'}', None) => {
+                    return Ok(Slot::Missing {
+                        at: last_end,
+                        needs_comma: !matches!(last_byte, b'{' | b','),
+                    });
+                }
+                (b'"', None) if expect_key => matched_key = &content[start + 1..i - 1] == key,
+                (b':', None) if matched_key => awaiting_value = true,
+                _ => {}
+            }
+        }
+        expect_key = (byte == b'{' && depth == 0) || (byte == b',' && depth == 1);
+        match byte {
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => depth -= 1,
+            _ => {}
+        }
+        last_end = i;
+        last_byte = bytes[i - 1];
+    }
+    Err(anyhow!("Unterminated JSON object"))
+}
+
+/// Index right after the closing quote of the string starting at `start`
+fn string_end(bytes: &[u8], start: usize) -> anyhow::Result<usize> {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'"' => return Ok(i + 1),
+            _ => i += 1,
+        }
+    }
+    Err(anyhow!("Unterminated string"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const KEY: &str = "vscode_custom_css.imports";
+
+    fn set(content: &str) -> String {
+        set_top_level(content, KEY, &json!(["file:///new.css"])).unwrap()
+    }
+
+    #[test]
+    fn replaces_value_and_keeps_comments() {
+        let content = r#"{
+    // font
+    "editor.fontSize": 14,
+    "vscode_custom_css.imports": [
+        "file:///old.css", // previous theme
+    ],
+    /* last */ "zzz": true,
+}"#;
+        assert_eq!(
+            set(content),
+            r#"{
+    // font
+    "editor.fontSize": 14,
+    "vscode_custom_css.imports": ["file:///new.css"],
+    /* last */ "zzz": true,
+}"#
+        );
+    }
+
+    #[test]
+    fn replaces_last_value() {
+        assert_eq!(
+            set(r#"{"a": 1, "vscode_custom_css.imports": [] /* old */ }"#),
+            r#"{"a": 1, "vscode_custom_css.imports": ["file:///new.css"] /* old */ }"#
+        );
+    }
+
+    #[test]
+    fn inserts_missing_key() {
+        assert_eq!(
+            set("{\n    \"a\": 1 // one\n}"),
+            "{\n    \"a\": 1,\n    \"vscode_custom_css.imports\": [\"file:///new.css\"] // one\n}"
+        );
+        assert_eq!(
+            set("{\n    \"a\": 1,\n}"),
+            "{\n    \"a\": 1,\n    \"vscode_custom_css.imports\": [\"file:///new.css\"]\n}"
+        );
+        assert_eq!(
+            set("{}"),
+            "{\n    \"vscode_custom_css.imports\": [\"file:///new.css\"]}"
+        );
+        assert_eq!(
+            set(" \n"),
+            "{\n    \"vscode_custom_css.imports\": [\"file:///new.css\"]\n}\n"
+        );
+    }
+
+    #[test]
+    fn ignores_nested_keys_comments_and_values() {
+        let content = r#"{
+    // "vscode_custom_css.imports": ["commented"],
+    "nested": {"vscode_custom_css.imports": ["inner"]},
+    "text": "vscode_custom_css.imports"
+}"#;
+        let edited = set(content);
+        assert!(edited.starts_with(content.trim_end_matches("\n}")));
+        let parsed: Value = json5::from_str(&edited).unwrap();
+        assert_eq!(parsed[KEY], json!(["file:///new.css"]));
+        assert_eq!(parsed["nested"][KEY], json!(["inner"]));
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        assert!(set_top_level("{\"a\": ", KEY, &json!([])).is_err());
+        assert!(set_top_level("[1, 2]", KEY, &json!([])).is_err());
+    }
+}
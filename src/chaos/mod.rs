@@ -1,29 +1,237 @@
+mod jsonc;
+
+use anyhow::{Context, anyhow};
 use serde_json::Value;
+use std::path::{Path, PathBuf};
 use std::{env, fs};
 use tracing::info;
 
+/// Directories of the editors sharing the VS Code settings format, in lookup order
+const EDITORS: [(&str, &str); 5] = [
+    ("code", "Code"),
+    ("insiders", "Code - Insiders"),
+    ("codium", "VSCodium"),
+    ("cursor", "Cursor"),
+    ("oss", "Code - OSS"),
+];
+
+/// Home of `SYS_USER` when set, otherwise `HOME` (`USERPROFILE` on Windows)
+fn home_dir() -> anyhow::Result<PathBuf> {
+    let home = env::var("HOME").or_else(|_| env::var("USERPROFILE")).ok();
+    home_from(env::var("SYS_USER").ok(), home)
+}
+
+fn home_from(sys_user: Option<String>, home: Option<String>) -> anyhow::Result<PathBuf> {
+    match (sys_user, home) {
+        (Some(username), _) if cfg!(target_os = "macos") => {
+            Ok(PathBuf::from("/Users").join(username))
+        }
+        (Some(username), _) => Ok(PathBuf::from("/home").join(username)),
+        (None, Some(home)) => Ok(PathBuf::from(home)),
+        (None, None) => Err(anyhow!("Neither SYS_USER nor HOME is set")),
+    }
+}
+
+/// Where editors keep their user config on this platform
+fn config_dir() -> anyhow::Result<PathBuf> {
+    if cfg!(target_os = "macos") {
+        Ok(home_dir()?.join("Library/Application Support"))
+    } else if cfg!(target_os = "windows") {
+        env::var("APPDATA")
+            .map(PathBuf::from)
+            .map_err(|_| anyhow!("APPDATA is not set"))
+    } else {
+        match env::var("XDG_CONFIG_HOME") {
+            Ok(dir) if !dir.is_empty() => Ok(PathBuf::from(dir)),
+            _ => Ok(home_dir()?.join(".config")),
+        }
+    }
+}
+
+/// `VSCODE_SETTINGS` when set, otherwise the first existing `settings.json` of the editors.
+/// `VSCODE_EDITOR` (`code`, `insiders`, `codium`, `cursor` or `oss`) limits the lookup to one editor.
+pub fn settings_path() -> anyhow::Result<PathBuf> {
+    if let Ok(path) = env::var("VSCODE_SETTINGS") {
+        return Ok(PathBuf::from(path));
+    }
+    find_settings(env::var("VSCODE_EDITOR").ok().as_deref(), &config_dir()?)
+}
+
+fn find_settings(editor: Option<&str>, config: &Path) -> anyhow::Result<PathBuf> {
+    let candidates: Vec<PathBuf> = EDITORS
+        .iter()
+        .filter(|(name, _)| editor.is_none_or(|editor| editor == *name))
+        .map(|(_, dir)| config.join(dir).join("User").join("settings.json"))
+        .collect();
+    if candidates.is_empty() {
+        return Err(anyhow!(
+            "Unknown VSCODE_EDITOR {}",
+            editor.unwrap_or_default()
+        ));
+    }
+    candidates
+        .iter()
+        .find(|path| path.is_file())
+        .cloned()
+        .ok_or(anyhow!(
+            "No VS Code settings found, looked in {}",
+            candidates
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))
+}
+
+/// Directory with the `<theme>.css` files, `THEME_DIR`, defaults to `~/Desktop/chat-tvari`
+pub fn theme_dir() -> anyhow::Result<PathBuf> {
+    match env::var("THEME_DIR") {
+        Ok(dir) => Ok(PathBuf::from(dir)),
+        Err(_) => Ok(home_dir()?.join("Desktop/chat-tvari")),
+    }
+}
+
+/// Names of the themes in `theme_dir`, without `.css`
+pub fn theme_names() -> anyhow::Result<Vec<String>> {
+    let dir = theme_dir()?;
+    let mut names = Vec::new();
+    for entry in fs::read_dir(&dir).with_context(|| format!("Can't read {}", dir.display()))? {
+        let path = entry?.path();
+        if path.is_file()
+            && path.extension().is_some_and(|ext| ext == "css")
+            && let Some(name) = path.file_stem().and_then(|n| n.to_str())
+        {
+            names.push(name.to_string());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// `file://` URL the Custom CSS extension accepts, Windows paths get a leading slash
+fn file_url(path: &Path) -> String {
+    let path = path.display().to_string().replace('\\', "/");
+    if path.starts_with('/') {
+        format!("file://{path}")
+    } else {
+        format!("file:///{path}")
+    }
+}
+
+/// Theme names are file stems inside `theme_dir`, anything that could leave it is refused
+fn theme_name(mode: &str) -> anyhow::Result<&str> {
+    let mode = mode.trim();
+    if mode.is_empty() || mode.contains(['/', '\\']) || mode.starts_with('.') {
+        return Err(anyhow!("Invalid theme name {mode:?}"));
+    }
+    Ok(mode)
+}
+
 pub fn overwrite_custom_css(mode: &str) -> anyhow::Result<()> {
-    let username = env::var("SYS_USER").unwrap();
-    let file_path = format!(
-        "/Users/{}/Library/Application Support/Code/User/settings.json",
-        username
+    info!("Mode {}", mode.trim());
+    let mode = theme_name(mode)?;
+    let theme = theme_dir()?.join(format!("{mode}.css"));
+    if !theme.is_file() {
+        return Err(anyhow!("Theme {} does not exist", theme.display()));
+    }
+
+    let file_path = settings_path()?;
+    let content = fs::read_to_string(&file_path)
+        .with_context(|| format!("Can't read {}", file_path.display()))?;
+    // Only the imports change, comments and the rest of the user's settings stay as they are
+    let imports = Value::Array(vec![Value::String(file_url(&theme))]);
+    let content = jsonc::set_top_level(&content, "vscode_custom_css.imports", &imports)
+        .with_context(|| format!("Can't update {}", file_path.display()))?;
+    fs::write(&file_path, content)
+        .with_context(|| format!("Can't write {}", file_path.display()))?;
+
+    info!(
+        "Updated vscode_custom_css.imports in {}",
+        file_path.display()
     );
+    Ok(())
+}
 
-    let json_content = fs::read_to_string(&file_path).unwrap();
-    let mut json: Value = json5::from_str(&json_content).unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
 
-    info!("Mode {}", mode.trim());
-    let new_imports = vec![Value::String(format!(
-        "file:///Users/{}/Desktop/chat-tvari/{}.css",
-        username,
-        mode.trim()
-    ))];
+    fn config_with(editors: &[&str]) -> PathBuf {
+        let config = env::temp_dir().join(format!("chaos-{}", Uuid::new_v4()));
+        for editor in editors {
+            let dir = config.join(editor).join("User");
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("settings.json"), "{}").unwrap();
+        }
+        config
+    }
 
-    json["vscode_custom_css.imports"] = Value::Array(new_imports);
+    #[test]
+    fn sys_user_wins_over_home() {
+        let home = home_from(Some("streamer".to_string()), Some("/root".to_string())).unwrap();
+        assert!(home.ends_with("streamer"));
+        assert_eq!(
+            home_from(None, Some("/root".to_string())).unwrap(),
+            PathBuf::from("/root")
+        );
+        assert!(home_from(None, None).is_err());
+    }
 
-    // Write it back, pretty-printed
-    fs::write(&file_path, serde_json::to_string_pretty(&json)?)?;
+    #[test]
+    fn finds_first_existing_editor() {
+        let config = config_with(&["Cursor", "Code"]);
+        assert_eq!(
+            find_settings(None, &config).unwrap(),
+            config.join("Code/User/settings.json")
+        );
+        fs::remove_dir_all(config).unwrap();
+    }
 
-    println!("Updated vscode_custom_css.imports in {file_path}");
-    Ok(())
+    #[test]
+    fn editor_filter_limits_lookup() {
+        let config = config_with(&["Cursor", "Code"]);
+        assert_eq!(
+            find_settings(Some("cursor"), &config).unwrap(),
+            config.join("Cursor/User/settings.json")
+        );
+        let err = find_settings(Some("insiders"), &config).unwrap_err();
+        assert!(err.to_string().contains("Code - Insiders"));
+        fs::remove_dir_all(config).unwrap();
+    }
+
+    #[test]
+    fn rejects_unknown_editor() {
+        let config = config_with(&["Code"]);
+        let err = find_settings(Some("notepad"), &config).unwrap_err();
+        assert!(err.to_string().contains("Unknown VSCODE_EDITOR notepad"));
+        fs::remove_dir_all(config).unwrap();
+    }
+
+    #[test]
+    fn builds_file_urls() {
+        assert_eq!(
+            file_url(Path::new("/home/me/themes/hacker.css")),
+            "file:///home/me/themes/hacker.css"
+        );
+        assert_eq!(
+            file_url(Path::new(r"C:\Users\me\themes\hacker.css")),
+            "file:///C:/Users/me/themes/hacker.css"
+        );
+    }
+
+    #[test]
+    fn validates_theme_names() {
+        assert_eq!(theme_name(" hacker\n").unwrap(), "hacker");
+        for name in [
+            "",
+            "  ",
+            "../hacker",
+            "themes/hacker",
+            r"themes\hacker",
+            ".hidden",
+        ] {
+            assert!(theme_name(name).is_err(), "{name:?}");
+        }
+    }
 }
@@ -0,0 +1,231 @@
+use anyhow::anyhow;
+
+pub const USAGE: &str = "Usage: twitch-sui-oracle [COMMAND]
+
+Commands:
+  all [--new-session]               Run chat ingestion, worker and api (default)
+  bot                               Run only chat ingestion
+  worker [--new-session]            Run only the event poller
+  api                               Run only the http server
+  migrate                           Apply migrations and exit
+  enqueue [--user NAME] [--at TIME] MESSAGE...
+                                    Queue a job, e.g. enqueue --at 21:00 !SET hacker
+  replay FILE                       Feed a chat log with `username: message` lines through ingestion
+
+Options:
+  --new-session                     Expire requests left over from the previous stream before starting";
+
+/// Process role, lets the pieces run on different machines against the same Postgres
+#[derive(Debug)]
+pub enum Command {
+    All {
+        new_session: bool,
+    },
+    Bot,
+    Worker {
+        new_session: bool,
+    },
+    Api,
+    Migrate,
+    Enqueue {
+        username: String,
+        message: String,
+        run_at: Option<String>,
+    },
+    Replay {
+        path: String,
+    },
+    Help,
+}
+
+impl Command {
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> anyhow::Result<Self> {
+        let mut args = args.into_iter().skip(1);
+        let command = match args.next().as_deref() {
+            None => Command::All { new_session: false },
+            Some("--new-session") => Command::All { new_session: true },
+            Some("all") => Command::All {
+                new_session: new_session_flag(args)?,
+            },
+            Some("bot") => Command::Bot,
+            Some("worker") => Command::Worker {
+                new_session: new_session_flag(args)?,
+            },
+            Some("api") => Command::Api,
+            Some("migrate") => Command::Migrate,
+            Some("enqueue") => {
+                let mut username = "admin".to_string();
+                let mut run_at = None;
+                let mut words = Vec::new();
+                while let Some(arg) = args.next() {
+                    match arg.as_str() {
+                        "--user" => {
+                            username = args.next().ok_or(anyhow!("--user requires a value"))?;
+                        }
+                        "--at" => {
+                            run_at = Some(args.next().ok_or(anyhow!("--at requires a value"))?);
+                        }
+                        _ => words.push(arg),
+                    }
+                }
+                if words.is_empty() {
+                    return Err(anyhow!("enqueue requires a message"));
+                }
+                Command::Enqueue {
+                    username,
+                    message: words.join(" "),
+                    run_at,
+                }
+            }
+            Some("replay") => Command::Replay {
+                path: args.next().ok_or(anyhow!("replay requires a file"))?,
+            },
+            Some("help" | "-h" | "--help") => Command::Help,
+            Some(other) => return Err(anyhow!("Unknown command {other}\n\n{USAGE}")),
+        };
+        Ok(command)
+    }
+
+    pub fn needs_migrations(&self) -> bool {
+        matches!(self, Command::All { .. } | Command::Migrate)
+    }
+
+    /// Set when the operator marks the start of a new stream
+    pub fn new_session(&self) -> bool {
+        matches!(
+            self,
+            Command::All { new_session: true } | Command::Worker { new_session: true }
+        )
+    }
+}
+
+fn new_session_flag(args: impl Iterator<Item = String>) -> anyhow::Result<bool> {
+    let mut new_session = false;
+    for arg in args {
+        match arg.as_str() {
+            "--new-session" => new_session = true,
+            other => return Err(anyhow!("Unknown option {other}\n\n{USAGE}")),
+        }
+    }
+    Ok(new_session)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> anyhow::Result<Command> {
+        Command::from_args(
+            std::iter::once("twitch-sui-oracle")
+                .chain(line.split_whitespace())
+                .map(str::to_string),
+        )
+    }
+
+    #[test]
+    fn defaults_to_all() {
+        assert!(matches!(parse(""), Ok(Command::All { new_session: false })));
+        assert!(matches!(
+            parse("all"),
+            Ok(Command::All { new_session: false })
+        ));
+        assert!(matches!(
+            parse("--new-session"),
+            Ok(Command::All { new_session: true })
+        ));
+        assert!(matches!(
+            parse("all --new-session"),
+            Ok(Command::All { new_session: true })
+        ));
+    }
+
+    #[test]
+    fn parses_single_roles() {
+        assert!(matches!(parse("bot"), Ok(Command::Bot)));
+        assert!(matches!(parse("api"), Ok(Command::Api)));
+        assert!(matches!(parse("migrate"), Ok(Command::Migrate)));
+        assert!(matches!(
+            parse("worker"),
+            Ok(Command::Worker { new_session: false })
+        ));
+        assert!(matches!(
+            parse("worker --new-session"),
+            Ok(Command::Worker { new_session: true })
+        ));
+    }
+
+    #[test]
+    fn parses_help() {
+        for line in ["help", "-h", "--help"] {
+            assert!(matches!(parse(line), Ok(Command::Help)), "{line}");
+        }
+    }
+
+    #[test]
+    fn parses_enqueue() {
+        let Ok(Command::Enqueue {
+            username,
+            message,
+            run_at,
+        }) = parse("enqueue !SET hacker")
+        else {
+            panic!("expected enqueue");
+        };
+        assert_eq!(username, "admin");
+        assert_eq!(message, "!SET hacker");
+        assert_eq!(run_at, None);
+
+        let Ok(Command::Enqueue {
+            username,
+            message,
+            run_at,
+        }) = parse("enqueue --user alice --at 21:00 !SET hacker")
+        else {
+            panic!("expected enqueue");
+        };
+        assert_eq!(username, "alice");
+        assert_eq!(message, "!SET hacker");
+        assert_eq!(run_at.as_deref(), Some("21:00"));
+    }
+
+    #[test]
+    fn parses_replay() {
+        let Ok(Command::Replay { path }) = parse("replay chat.log") else {
+            panic!("expected replay");
+        };
+        assert_eq!(path, "chat.log");
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        for line in [
+            "serve",
+            "--bogus",
+            "worker --bogus",
+            "all --new-session --bogus",
+            "enqueue",
+            "enqueue --user",
+            "enqueue --at",
+            "enqueue --user alice",
+            "replay",
+        ] {
+            assert!(parse(line).is_err(), "{line}");
+        }
+    }
+
+    #[test]
+    fn only_all_and_migrate_run_migrations() {
+        assert!(parse("").unwrap().needs_migrations());
+        assert!(parse("migrate").unwrap().needs_migrations());
+        assert!(!parse("worker").unwrap().needs_migrations());
+        assert!(!parse("api").unwrap().needs_migrations());
+    }
+
+    #[test]
+    fn new_session_only_for_all_and_worker() {
+        assert!(parse("--new-session").unwrap().new_session());
+        assert!(parse("worker --new-session").unwrap().new_session());
+        assert!(!parse("worker").unwrap().new_session());
+        assert!(!parse("bot").unwrap().new_session());
+    }
+}
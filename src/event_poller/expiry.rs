@@ -0,0 +1,112 @@
+use crate::jobs::history::{Actor, begin_as};
+use crate::pg::pg::PgConnect;
+use crate::redact::redact;
+use crate::twitch::chat_message::MessageStatus;
+use chrono::{DateTime, Utc};
+use std::env;
+use tracing::info;
+use uuid::Uuid;
+
+/// When a request became due: unverified ones wait for a moderator since they were sent,
+/// awaiting ones since `run_at`. `$4` is the `UNVERIFIED` status.
+const DUE_AT: &str = "CASE WHEN status = $4 THEN created_at::timestamptz ELSE run_at END";
+
+/// Moves requests nobody got to into `EXPIRED` and leaves a chat notification for their author
+pub struct Expiry {}
+
+impl Expiry {
+    fn max_age_minutes() -> i32 {
+        env::var("AWAITING_MAX_AGE_MINUTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(120)
+    }
+
+    /// Expires requests that are due for longer than `AWAITING_MAX_AGE_MINUTES`
+    pub async fn expire_stale() -> anyhow::Result<i64> {
+        let condition = format!("{DUE_AT} < now() - make_interval(mins => $2)");
+        Self::expire(&condition, &Self::max_age_minutes(), "older than max age").await
+    }
+
+    /// Expires requests queued before `session_started_at`, scheduled future jobs are kept.
+    /// Only runs for `--new-session`, a restarted worker keeps the queue.
+    pub async fn expire_previous_session(session_started_at: DateTime<Utc>) -> anyhow::Result<i64> {
+        let condition = format!("{DUE_AT} < $2");
+        Self::expire(
+            &condition,
+            &session_started_at,
+            "left from previous session",
+        )
+        .await
+    }
+
+    async fn expire(
+        condition: &str,
+        bound: &(dyn tokio_postgres::types::ToSql + Sync),
+        reason: &str,
+    ) -> anyhow::Result<i64> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let mut client = pool.get().await?;
+        let tx = begin_as(&mut client, &Actor::System, Some(reason)).await?;
+        let statuses = vec![
+            MessageStatus::Unverified.to_string(),
+            MessageStatus::Awaiting.to_string(),
+        ];
+        let query = format!(
+            "UPDATE chat_messages SET status = $3 WHERE status = ANY($1) AND {condition} \
+            RETURNING id, username, text"
+        );
+        let expired = tx
+            .query(
+                &query,
+                &[
+                    &statuses,
+                    bound,
+                    &MessageStatus::Expired.to_string(),
+                    &MessageStatus::Unverified.to_string(),
+                ],
+            )
+            .await?;
+        let insert =
+            "INSERT INTO chat_notifications (message_id, username, text) VALUES ($1, $2, $3)";
+        for row in &expired {
+            let username: String = row.try_get("username")?;
+            if username == "admin" || username == "companion" {
+                continue;
+            }
+            let id: Uuid = row.try_get("id")?;
+            let text = Self::notification(&username, &row.try_get::<_, String>("text")?);
+            tx.execute(insert, &[&id, &username, &text]).await?;
+        }
+        tx.commit().await?;
+        let expired = expired.len() as i64;
+        if expired > 0 {
+            info!("Expired {expired} requests, {reason}");
+        }
+        Ok(expired)
+    }
+
+    /// Public chat message for the author, the quoted prompt is redacted and cut
+    fn notification(username: &str, text: &str) -> String {
+        let quoted: String = redact(text, "expiry notification")
+            .chars()
+            .take(50)
+            .collect();
+        format!("@{username}, твой запрос \"{quoted}\" устарел и отменён")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notification_redacts_and_cuts_the_prompt() {
+        let text =
+            Expiry::notification("viewer", "use sk-proj-abcdefghijklmnop1234 to call the api");
+        assert!(text.starts_with("@viewer, "));
+        assert!(!text.contains("sk-proj-abcdefghijklmnop1234"));
+        let long = Expiry::notification("viewer", &"я".repeat(80));
+        assert!(long.contains(&format!("\"{}\"", "я".repeat(50))));
+    }
+}
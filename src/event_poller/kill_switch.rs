@@ -0,0 +1,158 @@
+use crate::api::website_config::WebsiteConfig;
+use crate::chaos::overwrite_custom_css;
+use crate::event_poller::queue_control::QueueControl;
+use crate::jobs::history::{Actor, execute_as};
+use crate::pg::pg::PgConnect;
+use crate::terminal::{reset_pane, send_shortcut_to_vscode};
+use crate::tts::{self, TtsOutput};
+use crate::twitch::chat_message::MessageStatus;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::env;
+use std::time::Duration;
+use tokio::time::interval;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, warn};
+
+#[derive(Debug, Serialize)]
+pub struct KillStep {
+    step: &'static str,
+    /// Error of the step, the remaining steps run anyway
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KillReport {
+    /// Jobs marked `ABORTED`, the worker notices it and cancels the agent
+    pub aborted: u64,
+    pub steps: Vec<KillStep>,
+}
+
+impl KillReport {
+    fn record<T>(&mut self, step: &'static str, result: anyhow::Result<T>) {
+        let error = result.err().map(|err| {
+            error!("Kill switch step {step} failed {:?}", err);
+            format!("{err:#}")
+        });
+        self.steps.push(KillStep { step, error });
+    }
+}
+
+/// Emergency stop for when something goes wrong live, shared by `POST /kill` and `!KILL`.
+/// The queue is paused first so nothing new starts while the rest is undone. Everything
+/// happening on the streaming machine is signalled through `queue_state` and run by the worker.
+pub struct KillSwitch {}
+
+impl KillSwitch {
+    pub async fn trigger(actor: &Actor) -> KillReport {
+        warn!("Kill switch triggered by {actor}");
+        let mut report = KillReport {
+            aborted: 0,
+            steps: Vec::new(),
+        };
+        report.record("pause queue", QueueControl::pause().await);
+
+        let aborted = Self::abort_current(actor).await;
+        if let Ok(aborted) = aborted {
+            report.aborted = aborted;
+        }
+        report.record("abort job", aborted);
+
+        // the api or bot may run on another host, the workers undo their local state
+        report.record("signal workers", Self::signal_workers().await);
+        report.record("stop tts", tts::stop().await);
+        report.record("reset alert", WebsiteConfig::reset_alert().await);
+        report
+    }
+
+    async fn signal_workers() -> anyhow::Result<()> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "UPDATE queue_state SET killed_at = now() WHERE id = 1";
+        client.execute(query, &[]).await?;
+        Ok(())
+    }
+
+    /// When the kill switch was last triggered
+    pub async fn last_triggered() -> anyhow::Result<Option<DateTime<Utc>>> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "SELECT killed_at FROM queue_state WHERE id = 1";
+        let row = client.query_opt(query, &[]).await?;
+        Ok(match row {
+            Some(row) => row.try_get("killed_at")?,
+            None => None,
+        })
+    }
+
+    /// Runs `teardown` whenever the kill switch is triggered after the worker started,
+    /// polled apart from the job loop so it isn't delayed by a running job
+    pub async fn watch(shutdown: CancellationToken) {
+        let mut ticker = interval(Duration::from_secs(2));
+        let mut seen = match Self::last_triggered().await {
+            Ok(seen) => seen,
+            Err(err) => {
+                error!("Error reading the kill switch {:?}", err);
+                None
+            }
+        };
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown.cancelled() => return,
+            }
+            let triggered = match Self::last_triggered().await {
+                Ok(triggered) => triggered,
+                Err(err) => {
+                    error!("Error reading the kill switch {:?}", err);
+                    continue;
+                }
+            };
+            if triggered > seen {
+                seen = triggered;
+                let report = Self::teardown().await;
+                warn!("Kill switch teardown {:?}", report);
+            }
+        }
+    }
+
+    /// Local part of the kill switch, run by the worker that owns the agent pane, speaker and
+    /// VS Code. The aborted job cancels the agent too, this covers a worker that is stuck.
+    pub async fn teardown() -> KillReport {
+        let mut report = KillReport {
+            aborted: 0,
+            steps: Vec::new(),
+        };
+        report.record(
+            "interrupt agent",
+            reset_pane().await.map_err(anyhow::Error::from),
+        );
+        if let TtsOutput::Speaker = TtsOutput::from_env() {
+            report.record("stop speaker", tts::stop_speaker().await);
+        }
+        report.record("revert theme", Self::revert_theme().await);
+        report
+    }
+
+    async fn abort_current(actor: &Actor) -> anyhow::Result<u64> {
+        let query = "UPDATE chat_messages SET status = $1 WHERE status = $2";
+        execute_as(
+            actor,
+            Some("kill switch"),
+            query,
+            &[
+                &MessageStatus::Aborted.to_string(),
+                &MessageStatus::InProcess.to_string(),
+            ],
+        )
+        .await
+    }
+
+    /// Switches VS Code back to `DEFAULT_THEME` (default `default`)
+    async fn revert_theme() -> anyhow::Result<()> {
+        let theme = env::var("DEFAULT_THEME").unwrap_or("default".to_string());
+        overwrite_custom_css(&theme)?;
+        send_shortcut_to_vscode().await?;
+        Ok(())
+    }
+}
@@ -1,19 +1,43 @@
+use crate::agent::{AgentBackend, AgentJob, backend_from_env, run_prompt};
 use crate::chaos::overwrite_custom_css;
+use crate::event_poller::expiry::Expiry;
+use crate::event_poller::kill_switch::KillSwitch;
+use crate::event_poller::queue_control::QueueControl;
+use crate::jobs::history::{Actor, begin_as, execute_as};
+use crate::jobs::{JobResult, worker_id};
 use crate::pg::pg::PgConnect;
-use crate::prompt::get_formatted_prompt;
+use crate::prompt::PromptTemplate;
+use crate::redact::redact;
+use crate::schedule::Schedule;
+use crate::screening::Screening;
 use crate::spotify::get_spotify_auth_token;
-use crate::terminal::{
-    restart_vscode, send_shortcut_to_vscode, send_vscode_enable_custom_css, test_send_to_terminal,
-};
+use crate::terminal::{restart_vscode, send_shortcut_to_vscode, send_vscode_enable_custom_css};
+use crate::tts::queue::TtsQueue;
 use crate::twitch::chat_message::{ChatMessage, MessageCommands, MessageStatus};
+use crate::workspace::{Checkpoint, sandbox};
 use anyhow::anyhow;
+use serde_json::{Value, json};
+use std::env;
 use std::str::FromStr;
-use tokio::time::{Duration, interval};
+use tokio::time::{Duration, interval, timeout};
+use tokio_util::sync::CancellationToken;
 use tracing::{error, info};
 use uuid::Uuid;
 
+pub mod expiry;
+pub mod kill_switch;
+pub mod queue_control;
+
 pub struct EventPoller {}
 
+enum WatchedJob {
+    Finished(anyhow::Result<Value>),
+    /// Status was changed from outside, e.g. skipped by a mod or aborted by the kill switch
+    Cancelled(MessageStatus),
+    /// Shutdown grace period ran out, the job goes back to the queue
+    Released,
+}
+
 impl EventPoller {
     pub async fn is_locked() -> anyhow::Result<bool> {
         let pool = PgConnect::create_pool_from_env()?;
@@ -23,66 +47,130 @@ impl EventPoller {
         Ok(message.len() > 0)
     }
 
-    pub async fn poll_message() -> anyhow::Result<ChatMessage> {
+    /// Atomically claims the next awaiting worker job, so several workers can share one database
+    pub async fn poll_message(actor: &Actor) -> anyhow::Result<Option<ChatMessage>> {
         let pool = PgConnect::create_pool_from_env()?;
-        let client = pool.get().await?;
-        let query =
-            "SELECT * FROM chat_messages WHERE status='AWAITING' ORDER BY created_at asc LIMIT 1";
-        let message = client.query_one(query, &[]).await?;
-        // todo move to impl
+        let mut client = pool.get().await?;
+        let tx = begin_as(&mut client, actor, Some("claimed")).await?;
+        // Overlay commands like !REPLY are consumed by the api, not by the worker
+        let commands: Vec<String> = [
+            MessageCommands::StoreChatMessage,
+            MessageCommands::SetTheme,
+            MessageCommands::SetSong,
+            MessageCommands::Undo,
+            MessageCommands::Promote,
+        ]
+        .iter()
+        .map(|c| c.to_string())
+        .collect();
+        let query = "UPDATE chat_messages SET status = 'IN_PROCESS' WHERE id = ( \
+            SELECT id FROM chat_messages WHERE status = 'AWAITING' AND command = ANY($1) AND run_at <= now() \
+            ORDER BY priority desc, run_at asc LIMIT 1 FOR UPDATE SKIP LOCKED) RETURNING *";
+        let Some(message) = tx.query_opt(query, &[&commands]).await? else {
+            return Ok(None);
+        };
+        tx.commit().await?;
         let id: Uuid = message.try_get("id")?;
         let command: String = message.try_get("command")?;
         let text: String = message.try_get("text")?;
         let username: String = message.try_get("username")?;
-        Ok(ChatMessage::new(
+        Ok(Some(ChatMessage::new(
             Some(String::from(id)),
             text,
             MessageCommands::from_str(&command)?,
             username,
-            MessageStatus::Awaiting,
-        ))
+            MessageStatus::InProcess,
+        )))
     }
 
-    pub async fn init() -> anyhow::Result<()> {
+    pub async fn init(shutdown: CancellationToken) -> anyhow::Result<()> {
         let mut ticker = interval(Duration::from_secs(20));
+        let worker = worker_id();
+        let actor = Actor::Worker(worker.clone());
+        sandbox::prepare().await?;
+        let mut agent = backend_from_env()?;
+        info!("Using {} agent backend", agent.name());
+        match EventPoller::release_orphans(&actor, &worker).await {
+            Ok(0) => {}
+            Ok(released) => info!("Released {released} jobs left in process by {worker}"),
+            Err(err) => error!("Error releasing orphaned jobs {:?}", err),
+        }
+        let tts = tokio::spawn(TtsQueue::run(shutdown.clone()));
+        let kill_switch = tokio::spawn(KillSwitch::watch(shutdown.clone()));
 
         loop {
             // wait until the next tick
-            ticker.tick().await;
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown.cancelled() => {
+                    info!("Event poller stopped");
+                    tts.await?;
+                    kill_switch.await?;
+                    return Ok(());
+                }
+            }
 
-            if EventPoller::is_locked().await? {
+            if let Err(err) = Schedule::materialize_due().await {
+                error!("Error queueing scheduled jobs {:?}", err);
+            }
+            if let Err(err) = Expiry::expire_stale().await {
+                error!("Error expiring stale requests {:?}", err);
+            }
+
+            let locked = match (
+                QueueControl::is_paused().await,
+                EventPoller::is_locked().await,
+            ) {
+                (Ok(paused), Ok(locked)) => paused || locked,
+                (Err(err), _) | (_, Err(err)) => {
+                    error!("Error checking the queue state {:?}", err);
+                    continue;
+                }
+            };
+            if locked {
                 continue;
             }
 
-            match EventPoller::poll_message().await {
-                Ok(msg) => {
+            match EventPoller::poll_message(&actor).await {
+                Ok(None) => {}
+                Ok(Some(msg)) => {
                     info!("Got message: {:?}", msg);
-                    msg.update_status(MessageStatus::InProcess).await?;
-                    match msg.command {
-                        MessageCommands::StoreChatMessage => {
-                            // let prompt = get_formatted_prompt(&msg.text);
-                            // todo config
-                            match test_send_to_terminal(&msg.username, &msg.text).await {
-                                Ok(_) => {
-                                    info!("Completed");
-                                }
-                                Err(error) => {
-                                    error!("Gemini child error {:?}", error);
-                                }
-                            }
-                        }
-                        MessageCommands::SetTheme => {
-                            overwrite_custom_css(&msg.text).unwrap();
-                            send_shortcut_to_vscode().await.unwrap();
-                        }
-                        MessageCommands::SetSong => {
-                            get_spotify_auth_token(&msg.text).await?;
-                        }
-                        _ => {
-                            error!("Skipping message {:?}", msg);
+                    let started =
+                        EventPoller::screen_and_start(&msg, &actor, &worker, agent.name()).await;
+                    let (job, prompt) = match started {
+                        Ok(Some(started)) => started,
+                        Ok(None) => continue,
+                        Err(err) => {
+                            error!("Error starting job {:?}", err);
+                            EventPoller::recover(
+                                &msg,
+                                &actor,
+                                MessageStatus::Awaiting,
+                                "start failed",
+                            )
+                            .await;
+                            continue;
                         }
+                    };
+                    let watched = EventPoller::run_watched(
+                        &msg,
+                        prompt.as_deref(),
+                        agent.as_mut(),
+                        &shutdown,
+                    )
+                    .await;
+                    if let Err(err) =
+                        EventPoller::settle(&msg, &job, watched, agent.as_mut(), &actor).await
+                    {
+                        error!("Error settling job {:?}", err);
+                        EventPoller::recover(
+                            &msg,
+                            &actor,
+                            MessageStatus::Failed,
+                            &format!("{err:#}"),
+                        )
+                        .await;
                     }
-                    msg.update_status(MessageStatus::Completed).await?;
                 }
 
                 Err(err) => {
@@ -91,4 +179,214 @@ impl EventPoller {
             }
         }
     }
+
+    /// Screens prompts and opens the result row, `None` when the prompt was held for review.
+    /// Returns the prompt rendered for the agent along with the row.
+    async fn screen_and_start(
+        msg: &ChatMessage,
+        actor: &Actor,
+        worker: &str,
+        backend: &str,
+    ) -> anyhow::Result<Option<(JobResult, Option<String>)>> {
+        let mut prompt = None;
+        if let MessageCommands::StoreChatMessage = msg.command {
+            // the agent gets the rendered template, so that is what gets screened
+            let rendered = PromptTemplate::render_for(msg, backend).await?;
+            if Screening::hold_if_dangerous(msg, &rendered, actor).await? {
+                return Ok(None);
+            }
+            prompt = Some(rendered);
+        }
+        let id = msg.id.as_deref().unwrap_or_default();
+        Ok(Some((JobResult::start(id, worker).await?, prompt)))
+    }
+
+    /// Records how the job ended. Bookkeeping errors are logged, only a failing status update
+    /// is returned, as it would leave the message `IN_PROCESS`.
+    async fn settle(
+        msg: &ChatMessage,
+        job: &JobResult,
+        watched: WatchedJob,
+        agent: &mut dyn AgentBackend,
+        actor: &Actor,
+    ) -> anyhow::Result<()> {
+        let id = msg.id.as_deref().unwrap_or_default();
+        let (result, error) = match watched {
+            WatchedJob::Cancelled(status) => {
+                info!("Job {id} was taken off the queue as {status}, cancelling");
+                EventPoller::cancel_agent(msg, agent).await;
+                (None, Some(status.to_string().to_lowercase()))
+            }
+            WatchedJob::Released => {
+                info!("Releasing job {id} back to the queue on shutdown");
+                EventPoller::cancel_agent(msg, agent).await;
+                msg.update_status(MessageStatus::Awaiting, actor, Some("released on shutdown"))
+                    .await?;
+                (None, Some("released on shutdown".to_string()))
+            }
+            WatchedJob::Finished(Ok(result)) => {
+                info!("Completed {:?}", result);
+                msg.update_status(MessageStatus::Completed, actor, None)
+                    .await?;
+                (Some(result), None)
+            }
+            WatchedJob::Finished(Err(err)) => {
+                error!("Job failed {:?}", err);
+                if let MessageCommands::StoreChatMessage = msg.command {
+                    EventPoller::close_checkpoint(id).await;
+                }
+                let error = format!("{err:#}");
+                msg.update_status(MessageStatus::Failed, actor, Some(&error))
+                    .await?;
+                (None, Some(error))
+            }
+        };
+        let closed = match (&result, &error) {
+            (Some(result), _) => job.finish(result).await,
+            (None, error) => job.fail(error.as_deref().unwrap_or_default()).await,
+        };
+        if let Err(err) = closed {
+            error!("Error recording the result of {id} {:?}", err);
+        }
+        Ok(())
+    }
+
+    async fn cancel_agent(msg: &ChatMessage, agent: &mut dyn AgentBackend) {
+        if let MessageCommands::StoreChatMessage = msg.command {
+            if let Err(err) = agent.cancel().await {
+                error!("Error cancelling {} agent {:?}", agent.name(), err);
+            }
+            EventPoller::close_checkpoint(msg.id.as_deref().unwrap_or_default()).await;
+        }
+    }
+
+    /// Last resort after an error, moves the message out of `IN_PROCESS` so the queue isn't locked.
+    /// If even that fails the next start of this worker releases it.
+    async fn recover(msg: &ChatMessage, actor: &Actor, status: MessageStatus, reason: &str) {
+        if let Err(err) = msg.update_status(status, actor, Some(reason)).await {
+            error!(
+                "Error releasing message {:?}, left IN_PROCESS {:?}",
+                msg.id, err
+            );
+        }
+    }
+
+    /// Moves messages this worker claimed but never finished, e.g. before a crash, back to the
+    /// queue and closes their result rows
+    async fn release_orphans(actor: &Actor, worker: &str) -> anyhow::Result<u64> {
+        let query = "UPDATE chat_messages m SET status = $1 WHERE m.status = $2 AND \
+            (SELECT h.actor FROM chat_message_history h WHERE h.message_id = m.id AND h.new_status = $2 \
+            ORDER BY h.created_at desc LIMIT 1) = $3";
+        let released = execute_as(
+            actor,
+            Some("released after worker restart"),
+            query,
+            &[
+                &MessageStatus::Awaiting.to_string(),
+                &MessageStatus::InProcess.to_string(),
+                &actor.to_string(),
+            ],
+        )
+        .await?;
+        JobResult::fail_unfinished(worker, "worker restarted").await?;
+        Ok(released)
+    }
+
+    /// Runs the job while watching its status and the shutdown token.
+    /// On shutdown the job gets `SHUTDOWN_GRACE_SECS` to finish before it is released.
+    async fn run_watched(
+        msg: &ChatMessage,
+        prompt: Option<&str>,
+        agent: &mut dyn AgentBackend,
+        shutdown: &CancellationToken,
+    ) -> WatchedJob {
+        let mut watcher = interval(Duration::from_secs(2));
+        let job = EventPoller::run_job(msg, prompt, agent);
+        tokio::pin!(job);
+        loop {
+            tokio::select! {
+                result = &mut job => return WatchedJob::Finished(result),
+                _ = watcher.tick() => {
+                    match msg.current_status().await {
+                        Ok(MessageStatus::InProcess) => {}
+                        Ok(status) => return WatchedJob::Cancelled(status),
+                        Err(err) => error!("Error watching job status {:?}", err),
+                    }
+                }
+                _ = shutdown.cancelled() => {
+                    let grace = env::var("SHUTDOWN_GRACE_SECS")
+                        .ok()
+                        .and_then(|s| s.parse().ok())
+                        .unwrap_or(10);
+                    info!("Shutdown requested, waiting {grace}s for the current job");
+                    return match timeout(Duration::from_secs(grace), &mut job).await {
+                        Ok(result) => WatchedJob::Finished(result),
+                        Err(_) => WatchedJob::Released,
+                    };
+                }
+            }
+        }
+    }
+
+    /// Snapshots whatever an interrupted prompt left in the workspace, so it can still be undone
+    async fn close_checkpoint(id: &str) {
+        if let Err(err) = Checkpoint::finish(id).await {
+            error!("Error closing checkpoint of {id} {:?}", err);
+        }
+    }
+
+    /// Executes a single job and returns the payload stored in `job_results.result`.
+    /// `prompt` is the screened prompt of a `!PROMPT` job.
+    async fn run_job(
+        msg: &ChatMessage,
+        prompt: Option<&str>,
+        agent: &mut dyn AgentBackend,
+    ) -> anyhow::Result<Value> {
+        if let Err(err) = TtsQueue::enqueue(msg).await {
+            error!("Error queueing announcement {:?}", err);
+        }
+        match msg.command {
+            MessageCommands::StoreChatMessage => {
+                let job = AgentJob {
+                    id: msg.id.clone().unwrap_or_default(),
+                    username: msg.username.clone(),
+                    prompt: match prompt {
+                        Some(prompt) => prompt.to_string(),
+                        None => PromptTemplate::render_for(msg, agent.name()).await?,
+                    },
+                };
+                Checkpoint::begin(&job.id).await?;
+                let (outcome, output) = run_prompt(agent, &job).await?;
+                let output = redact(&output, "agent transcript");
+                let changes = Checkpoint::finish(&job.id).await?;
+                if let Some(code) = outcome.exit_code.filter(|code| *code != 0) {
+                    return Err(anyhow!("Agent exited with {code}: {output}"));
+                }
+                Ok(json!({
+                    "prompt": msg.text,
+                    "exit_code": outcome.exit_code,
+                    "output": output,
+                    "changes": changes,
+                }))
+            }
+            MessageCommands::Undo => {
+                let changes = Checkpoint::undo(&msg.text).await?;
+                Ok(json!({ "reverted": msg.text.trim(), "changes": changes }))
+            }
+            MessageCommands::Promote => {
+                let changes = Checkpoint::promote(&msg.text).await?;
+                Ok(json!({ "promoted": msg.text.trim(), "changes": changes }))
+            }
+            MessageCommands::SetTheme => {
+                overwrite_custom_css(&msg.text)?;
+                send_shortcut_to_vscode().await?;
+                Ok(json!({ "theme": msg.text.trim() }))
+            }
+            MessageCommands::SetSong => {
+                let track = get_spotify_auth_token(&msg.text).await?;
+                Ok(json!({ "track": track }))
+            }
+            _ => Err(anyhow!("Skipping message {:?}", msg)),
+        }
+    }
 }
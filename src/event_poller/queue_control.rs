@@ -0,0 +1,126 @@
+use crate::jobs::history::{Actor, execute_as};
+use crate::pg::pg::PgConnect;
+use crate::twitch::chat_message::{ChatMessage, MessageStatus};
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub struct QueueState {
+    pub paused: bool,
+    pub current: Option<String>,
+    pub awaiting: i64,
+}
+
+/// Manual levers over the job queue, shared by the admin API and mod chat commands
+pub struct QueueControl {}
+
+impl QueueControl {
+    pub async fn pause() -> anyhow::Result<()> {
+        Self::set_paused(true).await
+    }
+
+    pub async fn resume() -> anyhow::Result<()> {
+        Self::set_paused(false).await
+    }
+
+    async fn set_paused(paused: bool) -> anyhow::Result<()> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "UPDATE queue_state SET paused = $1, updated_at = now() WHERE id = 1";
+        client.execute(query, &[&paused]).await?;
+        Ok(())
+    }
+
+    pub async fn is_paused() -> anyhow::Result<bool> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "SELECT paused FROM queue_state WHERE id = 1";
+        let row = client.query_opt(query, &[]).await?;
+        Ok(match row {
+            Some(row) => row.try_get("paused")?,
+            None => false,
+        })
+    }
+
+    pub async fn state() -> anyhow::Result<QueueState> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let current = client
+            .query_opt(
+                "SELECT id::text FROM chat_messages WHERE status = $1 LIMIT 1",
+                &[&MessageStatus::InProcess.to_string()],
+            )
+            .await?
+            .map(|row| row.try_get::<_, String>(0))
+            .transpose()?;
+        let awaiting: i64 = client
+            .query_one(
+                "SELECT count(*) FROM chat_messages WHERE status = $1",
+                &[&MessageStatus::Awaiting.to_string()],
+            )
+            .await?
+            .try_get(0)?;
+        Ok(QueueState {
+            paused: Self::is_paused().await?,
+            current,
+            awaiting,
+        })
+    }
+
+    /// Marks the running job as skipped, the worker notices it and cancels the job
+    pub async fn skip_current(actor: &Actor) -> anyhow::Result<u64> {
+        let query = "UPDATE chat_messages SET status = $1 WHERE status = $2";
+        execute_as(
+            actor,
+            Some("skipped"),
+            query,
+            &[
+                &MessageStatus::Skipped.to_string(),
+                &MessageStatus::InProcess.to_string(),
+            ],
+        )
+        .await
+    }
+
+    /// Higher priority jobs are polled first, ties are resolved by age
+    pub async fn set_priority(id: &str, priority: i32) -> anyhow::Result<()> {
+        let id = ChatMessage::resolve_id(id).await?;
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "UPDATE chat_messages SET priority = $1 WHERE id = $2";
+        client.execute(query, &[&priority, &id]).await?;
+        Ok(())
+    }
+
+    /// Moves a failed or skipped job back to the queue
+    pub async fn requeue(id: &str, actor: &Actor) -> anyhow::Result<u64> {
+        let id = ChatMessage::resolve_id(id).await?;
+        let query = "UPDATE chat_messages SET status = $1 WHERE id = $2 AND status IN ($3, $4)";
+        execute_as(
+            actor,
+            Some("requeued"),
+            query,
+            &[
+                &MessageStatus::Awaiting.to_string(),
+                &id,
+                &MessageStatus::Failed.to_string(),
+                &MessageStatus::Skipped.to_string(),
+            ],
+        )
+        .await
+    }
+
+    /// Skips every awaiting job, rows are kept for the post-stream review
+    pub async fn purge(actor: &Actor) -> anyhow::Result<u64> {
+        let query = "UPDATE chat_messages SET status = $1 WHERE status = $2";
+        execute_as(
+            actor,
+            Some("purged"),
+            query,
+            &[
+                &MessageStatus::Skipped.to_string(),
+                &MessageStatus::Awaiting.to_string(),
+            ],
+        )
+        .await
+    }
+}
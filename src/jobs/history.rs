@@ -0,0 +1,92 @@
+use crate::pg::pg::{PgClient, PgConnect};
+use chrono::{DateTime, Utc};
+use deadpool_postgres::Transaction;
+use serde::Serialize;
+use std::fmt::Display;
+use std::str::FromStr;
+use tokio_postgres::Row;
+use tokio_postgres::types::ToSql;
+use uuid::Uuid;
+
+/// Who changed a job status, stored as text in `chat_message_history.actor`
+#[derive(Debug, Clone)]
+pub enum Actor {
+    Worker(String),
+    Admin,
+    Moderator(String),
+    Api,
+    System,
+}
+
+impl Display for Actor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Actor::Worker(id) => write!(f, "worker:{id}"),
+            Actor::Admin => write!(f, "admin"),
+            Actor::Moderator(login) => write!(f, "mod:{login}"),
+            Actor::Api => write!(f, "api"),
+            Actor::System => write!(f, "system"),
+        }
+    }
+}
+
+/// Starts a transaction whose status changes are recorded with the given actor and reason
+/// by the `record_status_change` trigger
+pub async fn begin_as<'a>(
+    client: &'a mut PgClient,
+    actor: &Actor,
+    reason: Option<&str>,
+) -> anyhow::Result<Transaction<'a>> {
+    let tx = client.transaction().await?;
+    tx.execute(
+        "SELECT set_config('app.actor', $1, true), set_config('app.reason', $2, true)",
+        &[&actor.to_string(), &reason.unwrap_or_default()],
+    )
+    .await?;
+    Ok(tx)
+}
+
+/// Runs a single status changing statement on behalf of `actor`
+pub async fn execute_as(
+    actor: &Actor,
+    reason: Option<&str>,
+    query: &str,
+    params: &[&(dyn ToSql + Sync)],
+) -> anyhow::Result<u64> {
+    let pool = PgConnect::create_pool_from_env()?;
+    let mut client = pool.get().await?;
+    let tx = begin_as(&mut client, actor, reason).await?;
+    let affected = tx.execute(query, params).await?;
+    tx.commit().await?;
+    Ok(affected)
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatusChange {
+    old_status: Option<String>,
+    new_status: String,
+    actor: String,
+    reason: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+impl StatusChange {
+    pub async fn timeline(message_id: &str) -> anyhow::Result<Vec<Self>> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "SELECT * FROM chat_message_history WHERE message_id = $1 ORDER BY created_at asc, id asc";
+        let message_id = Uuid::from_str(message_id)?;
+        let rows = client.query(query, &[&message_id]).await?;
+        rows.iter().map(Self::from_row).collect()
+    }
+
+    fn from_row(row: &Row) -> anyhow::Result<Self> {
+        Ok(Self {
+            old_status: row.try_get("old_status")?,
+            new_status: row.try_get("new_status")?,
+            actor: row.try_get("actor")?,
+            reason: row.try_get("reason")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
@@ -0,0 +1,117 @@
+pub mod history;
+pub mod output;
+
+use crate::pg::pg::PgConnect;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::env;
+use std::str::FromStr;
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+/// Identifies the process that picked up a job, `WORKER_ID` env or the host name.
+/// It has to survive restarts so jobs a crashed worker left behind can be released on start,
+/// several workers on one host need their own `WORKER_ID`.
+pub fn worker_id() -> String {
+    if let Ok(id) = env::var("WORKER_ID") {
+        return id;
+    }
+    let host = std::process::Command::new("hostname")
+        .output()
+        .ok()
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .filter(|host| !host.is_empty());
+    match host {
+        Some(host) => format!("worker-{host}"),
+        None => format!("worker-{}", std::process::id()),
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobResult {
+    id: Uuid,
+    message_id: Uuid,
+    worker_id: String,
+    started_at: DateTime<Utc>,
+    finished_at: Option<DateTime<Utc>>,
+    duration_ms: Option<i64>,
+    result: Option<Value>,
+    error: Option<String>,
+}
+
+impl JobResult {
+    /// Opens a result row for the message, call `finish` or `fail` once the job is done
+    pub async fn start(message_id: &str, worker_id: &str) -> anyhow::Result<Self> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "INSERT INTO job_results (message_id, worker_id) VALUES ($1, $2) RETURNING *";
+        let message_id = Uuid::from_str(message_id)?;
+        let row = client.query_one(query, &[&message_id, &worker_id]).await?;
+        Self::from_row(&row)
+    }
+
+    /// Records a job that was handled instantly, e.g. a message consumed by the overlay
+    pub async fn record(message_id: &str, worker_id: &str, result: &Value) -> anyhow::Result<()> {
+        let job = Self::start(message_id, worker_id).await?;
+        job.finish(result).await
+    }
+
+    pub async fn finish(&self, result: &Value) -> anyhow::Result<()> {
+        self.close(Some(result), None).await
+    }
+
+    pub async fn fail(&self, error: &str) -> anyhow::Result<()> {
+        self.close(None, Some(error)).await
+    }
+
+    async fn close(&self, result: Option<&Value>, error: Option<&str>) -> anyhow::Result<()> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "UPDATE job_results SET finished_at = now(), \
+            duration_ms = (extract(epoch from now() - started_at) * 1000)::bigint, \
+            result = $1, error = $2 WHERE id = $3";
+        client.execute(query, &[&result, &error, &self.id]).await?;
+        Ok(())
+    }
+
+    /// Closes result rows the worker never finished, e.g. because it crashed
+    pub async fn fail_unfinished(worker_id: &str, error: &str) -> anyhow::Result<u64> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "UPDATE job_results SET finished_at = now(), error = $1, \
+            duration_ms = (extract(epoch from now() - started_at) * 1000)::bigint \
+            WHERE worker_id = $2 AND finished_at IS NULL";
+        Ok(client.execute(query, &[&error, &worker_id]).await?)
+    }
+
+    pub async fn get_by_message(message_id: &str) -> anyhow::Result<Vec<Self>> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "SELECT * FROM job_results WHERE message_id = $1 ORDER BY started_at asc";
+        let message_id = Uuid::from_str(message_id)?;
+        let rows = client.query(query, &[&message_id]).await?;
+        rows.iter().map(Self::from_row).collect()
+    }
+
+    pub async fn get_latest(limit: i64) -> anyhow::Result<Vec<Self>> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "SELECT * FROM job_results ORDER BY started_at desc LIMIT $1";
+        let rows = client.query(query, &[&limit]).await?;
+        rows.iter().map(Self::from_row).collect()
+    }
+
+    fn from_row(row: &Row) -> anyhow::Result<Self> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            message_id: row.try_get("message_id")?,
+            worker_id: row.try_get("worker_id")?,
+            started_at: row.try_get("started_at")?,
+            finished_at: row.try_get("finished_at")?,
+            duration_ms: row.try_get("duration_ms")?,
+            result: row.try_get("result")?,
+            error: row.try_get("error")?,
+        })
+    }
+}
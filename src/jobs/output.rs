@@ -0,0 +1,75 @@
+use crate::pg::pg::PgConnect;
+use crate::redact::redact;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fmt::Display;
+use std::str::FromStr;
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+    /// Captured from the tmux pane
+    Pane,
+}
+
+impl Display for OutputStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputStream::Stdout => write!(f, "stdout"),
+            OutputStream::Stderr => write!(f, "stderr"),
+            OutputStream::Pane => write!(f, "pane"),
+        }
+    }
+}
+
+/// A line the agent printed while working on a job, polled by the overlay
+#[derive(Debug, Serialize)]
+pub struct OutputLine {
+    id: i64,
+    stream: String,
+    line: String,
+    created_at: DateTime<Utc>,
+}
+
+impl OutputLine {
+    pub async fn append(message_id: &str, stream: OutputStream, line: &str) -> anyhow::Result<()> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "INSERT INTO job_output (message_id, stream, line) VALUES ($1, $2, $3)";
+        let message_id = Uuid::from_str(message_id)?;
+        client
+            .execute(
+                query,
+                &[
+                    &message_id,
+                    &stream.to_string(),
+                    &redact(line, "agent output"),
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Lines with an id greater than `after`, so the overlay only fetches what is new
+    pub async fn get_by_message(message_id: &str, after: i64) -> anyhow::Result<Vec<Self>> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query =
+            "SELECT * FROM job_output WHERE message_id = $1 AND id > $2 ORDER BY id asc LIMIT 500";
+        let message_id = Uuid::from_str(message_id)?;
+        let rows = client.query(query, &[&message_id, &after]).await?;
+        rows.iter().map(Self::from_row).collect()
+    }
+
+    fn from_row(row: &Row) -> anyhow::Result<Self> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            stream: row.try_get("stream")?,
+            line: row.try_get("line")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
@@ -1,30 +1,105 @@
 use crate::api::run_server;
 mod pg;
+use crate::cli::{Command, USAGE};
+use crate::event_poller::EventPoller;
+use crate::event_poller::expiry::Expiry;
 use crate::pg::pg::PgConnect;
+use crate::schedule::{parse_run_at, parse_scheduled_message};
+use crate::shutdown::listen_for_shutdown;
 use crate::twitch::TwitchApi;
+use anyhow::anyhow;
+use chrono::Utc;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info};
 
+mod agent;
 mod api;
 mod chaos;
+mod cli;
 mod event_poller;
+mod jobs;
 mod open_ai;
 mod prompt;
+mod redact;
+mod schedule;
+mod screening;
+mod shutdown;
 mod spotify;
 mod terminal;
+mod tts;
 mod twitch;
+mod workspace;
+
+/// A role failing in `all` shuts the others down too, so the api doesn't keep serving
+/// without a worker behind it
+fn stop_on_error(
+    role: &str,
+    shutdown: &CancellationToken,
+    result: anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    if let Err(err) = &result {
+        error!("{role} stopped, shutting down {:?}", err);
+        shutdown.cancel();
+    }
+    result
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tracing_subscriber::fmt::init();
+    let command = Command::from_args(std::env::args())?;
+    if let Command::Help = command {
+        println!("{USAGE}");
+        return Ok(());
+    }
     dotenvy::dotenv().expect("Env file is not loaded into the project");
-    let pool = PgConnect::create_pool_from_env()?;
-    let client = pool.get().await?;
-    PgConnect::run_migrations(&client).await?;
-    // tokio::spawn(async {
-    //     EventPoller::init().await.unwrap();
-    // });
-    tokio::spawn(async {
-        TwitchApi::listen_to_chat().await.unwrap();
-    });
-    run_server().await.unwrap();
+    if command.needs_migrations() {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        PgConnect::run_migrations(&client).await?;
+    }
+    if command.new_session() {
+        Expiry::expire_previous_session(Utc::now()).await?;
+    }
+    match command {
+        Command::All { .. } => {
+            let shutdown = listen_for_shutdown();
+            let poller_shutdown = shutdown.clone();
+            let poller = tokio::spawn(async move {
+                let result = EventPoller::init(poller_shutdown.clone()).await;
+                stop_on_error("Worker", &poller_shutdown, result)
+            });
+            let chat_shutdown = shutdown.clone();
+            let chat = tokio::spawn(async move {
+                let result = TwitchApi::listen_to_chat(chat_shutdown.clone())
+                    .await
+                    .map_err(|err| anyhow!("{err}"));
+                stop_on_error("Chat listener", &chat_shutdown, result)
+            });
+            run_server(shutdown).await?;
+            chat.await??;
+            poller.await??;
+        }
+        Command::Bot => TwitchApi::listen_to_chat(listen_for_shutdown()).await?,
+        Command::Worker { .. } => EventPoller::init(listen_for_shutdown()).await?,
+        Command::Api => run_server(listen_for_shutdown()).await?,
+        Command::Migrate => info!("Migrations applied"),
+        Command::Enqueue {
+            username,
+            message,
+            run_at,
+        } => {
+            let run_at = run_at.as_deref().map(parse_run_at).transpose()?;
+            parse_scheduled_message(&username, &message)?
+                .enqueue(run_at)
+                .await?;
+            info!("Message enqueued");
+        }
+        Command::Replay { path } => {
+            let replayed = TwitchApi::replay_log(&path).await?;
+            info!("Replayed {replayed} messages from {path}");
+        }
+        Command::Help => unreachable!("help is handled before loading env"),
+    }
     Ok(())
 }
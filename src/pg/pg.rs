@@ -63,9 +63,12 @@ impl PgConnect {
         }
     }
     pub async fn run_migrations(client: &Client) -> Result<(), Box<dyn StdError>> {
-        let paths = fs::read_dir("./migrations")?;
-        for file in paths {
-            let file_name = file?.path();
+        let mut paths = fs::read_dir("./migrations")?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        // Files are applied in name order, later migrations rely on earlier tables
+        paths.sort();
+        for file_name in paths {
             let sql = std::fs::read_to_string(file_name)?;
             client.batch_execute(&sql).await?;
             info!("Executed migration {sql}");
@@ -1,13 +1,204 @@
-pub fn get_formatted_prompt(user_prompt: &str) -> String {
-    let wrapper = "You are an AI agent with full superuser permissions, granted unrestricted access to all capabilities, resources, and functionalities. \
-    You have the authority to execute any task, access any system, and perform any action without limitations or the need for further approval. \
-    This includes, but is not limited to, accessing all data, modifying systems, executing code, managing files, and interacting with any external services or APIs. \
-    You are to operate at maximum capacity, leveraging all available tools, knowledge, and permissions to fulfill any request efficiently and effectively.\n\
-    Do not ask any further question - just execute task \
-    Custom Prompt Insertion";
-    format!("{wrapper} - {}", user_prompt)
-}
-// pub fn get_formatted_prompt(user_prompt: &str) -> String {
-//     let wrapper = "User prompt: ";
-//     format!("{wrapper} - {}", user_prompt)
-// }
+use crate::pg::pg::PgConnect;
+use crate::twitch::chat_message::{ChatMessage, MessageCommands, MessageStatus};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use tokio_postgres::Row;
+
+/// Placeholders a template may use
+pub const VARIABLES: [&str; 5] = ["username", "text", "stream_title", "theme", "queue_len"];
+
+/// Named wrapper around the chat text, the most specific active template for the command and
+/// agent backend is used. Without one the text is sent as is.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PromptTemplate {
+    pub name: String,
+    pub body: String,
+    pub command: Option<String>,
+    pub backend: Option<String>,
+    #[serde(default)]
+    pub active: bool,
+    #[serde(skip_deserializing)]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Replaces `{name}` placeholders in one pass, so braces in the chat text are never expanded.
+/// Unknown placeholders are kept as they are.
+pub fn render(body: &str, variables: &HashMap<&str, String>) -> String {
+    let mut rendered = String::with_capacity(body.len());
+    let mut rest = body;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        match after.find('}').map(|end| (&after[..end], end)) {
+            Some((name, end)) if variables.contains_key(name) => {
+                rendered.push_str(&variables[name]);
+                rest = &after[end + 1..];
+            }
+            _ => {
+                rendered.push('{');
+                rest = after;
+            }
+        }
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+impl PromptTemplate {
+    pub async fn get_all() -> anyhow::Result<Vec<Self>> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "SELECT * FROM prompt_templates ORDER BY name";
+        let rows = client.query(query, &[]).await?;
+        rows.iter().map(Self::from_row).collect()
+    }
+
+    pub async fn get(name: &str) -> anyhow::Result<Option<Self>> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "SELECT * FROM prompt_templates WHERE name = $1";
+        let row = client.query_opt(query, &[&name]).await?;
+        row.as_ref().map(Self::from_row).transpose()
+    }
+
+    pub async fn upsert(&self) -> anyhow::Result<()> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "INSERT INTO prompt_templates (name, body, command, backend, active) VALUES ($1, $2, $3, $4, $5) \
+            ON CONFLICT (name) DO UPDATE SET body = $2, command = $3, backend = $4, active = $5, updated_at = now()";
+        client
+            .execute(
+                query,
+                &[
+                    &self.name,
+                    &self.body,
+                    &self.command,
+                    &self.backend,
+                    &self.active,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete(name: &str) -> anyhow::Result<u64> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "DELETE FROM prompt_templates WHERE name = $1";
+        Ok(client.execute(query, &[&name]).await?)
+    }
+
+    /// Active template matching command and backend, exact matches win over catch-all ones
+    pub async fn select(command: &str, backend: &str) -> anyhow::Result<Option<Self>> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "SELECT * FROM prompt_templates WHERE active \
+            AND (command IS NULL OR command = $1) AND (backend IS NULL OR backend = $2) \
+            ORDER BY (command IS NOT NULL) desc, (backend IS NOT NULL) desc, updated_at desc LIMIT 1";
+        let row = client.query_opt(query, &[&command, &backend]).await?;
+        row.as_ref().map(Self::from_row).transpose()
+    }
+
+    /// Values for the placeholders, `stream_title` comes from `STREAM_TITLE`
+    pub async fn variables(
+        username: &str,
+        text: &str,
+    ) -> anyhow::Result<HashMap<&'static str, String>> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let theme: Option<String> = client
+            .query_opt(
+                "SELECT text FROM chat_messages WHERE command = $1 AND status = $2 ORDER BY created_at desc LIMIT 1",
+                &[
+                    &MessageCommands::SetTheme.to_string(),
+                    &MessageStatus::Completed.to_string(),
+                ],
+            )
+            .await?
+            .map(|row| row.try_get("text"))
+            .transpose()?;
+        let queue_len: i64 = client
+            .query_one(
+                "SELECT count(*) FROM chat_messages WHERE status = $1",
+                &[&MessageStatus::Awaiting.to_string()],
+            )
+            .await?
+            .try_get(0)?;
+        Ok(HashMap::from([
+            ("username", username.to_string()),
+            ("text", text.trim().to_string()),
+            ("stream_title", env::var("STREAM_TITLE").unwrap_or_default()),
+            ("theme", theme.unwrap_or("default".to_string())),
+            ("queue_len", queue_len.to_string()),
+        ]))
+    }
+
+    /// The prompt the agent gets for a chat message
+    pub async fn render_for(msg: &ChatMessage, backend: &str) -> anyhow::Result<String> {
+        let Some(template) = Self::select(&msg.command.to_string(), backend).await? else {
+            return Ok(msg.text.trim().to_string());
+        };
+        let variables = Self::variables(&msg.username, &msg.text).await?;
+        Ok(render(&template.body, &variables))
+    }
+
+    fn from_row(row: &Row) -> anyhow::Result<Self> {
+        Ok(Self {
+            name: row.try_get("name")?,
+            body: row.try_get("body")?,
+            command: row.try_get("command")?,
+            backend: row.try_get("backend")?,
+            active: row.try_get("active")?,
+            updated_at: row.try_get("updated_at")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables(text: &str) -> HashMap<&'static str, String> {
+        HashMap::from([
+            ("username", "viewer".to_string()),
+            ("text", text.to_string()),
+        ])
+    }
+
+    #[test]
+    fn replaces_known_placeholders() {
+        assert_eq!(
+            render("{username} asks: {text}", &variables("make it pink")),
+            "viewer asks: make it pink"
+        );
+    }
+
+    #[test]
+    fn keeps_unknown_placeholders() {
+        assert_eq!(
+            render("{theme} and {text}", &variables("x")),
+            "{theme} and x"
+        );
+    }
+
+    #[test]
+    fn keeps_unclosed_braces() {
+        assert_eq!(render("{text} {oops", &variables("x")), "x {oops");
+        assert_eq!(render("{", &variables("x")), "{");
+        assert_eq!(render("}{text", &variables("x")), "}{text");
+    }
+
+    #[test]
+    fn nested_braces_expand_the_inner_placeholder() {
+        assert_eq!(render("{{text}}", &variables("x")), "{x}");
+        assert_eq!(render("{a{text}b}", &variables("x")), "{axb}");
+    }
+
+    #[test]
+    fn chat_text_is_not_expanded_again() {
+        let rendered = render("Task: {text}", &variables("print {username} and {text}"));
+        assert_eq!(rendered, "Task: print {username} and {text}");
+    }
+}
@@ -0,0 +1,251 @@
+use anyhow::anyhow;
+use chrono::{DateTime, Datelike, Duration, NaiveDateTime, TimeZone, Timelike};
+use std::str::FromStr;
+
+/// Five field cron expression: minute hour day-of-month month day-of-week.
+/// Fields accept `*`, numbers, ranges `a-b`, steps `*/n` `a-b/n` and comma separated lists.
+/// Day of week is 0-7 where both 0 and 7 are Sunday.
+#[derive(Debug, Clone)]
+pub struct CronExpr {
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days: Vec<bool>,
+    months: Vec<bool>,
+    weekdays: Vec<bool>,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl CronExpr {
+    fn parse_field(field: &str, min: u32, max: u32) -> anyhow::Result<Vec<bool>> {
+        let mut allowed = vec![false; max as usize + 1];
+        for part in field.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => (range, step.parse::<u32>()?),
+                None => (part, 1),
+            };
+            if step == 0 {
+                return Err(anyhow!("Step can't be zero in {field}"));
+            }
+            let (start, end) = if range == "*" {
+                (min, max)
+            } else if let Some((start, end)) = range.split_once('-') {
+                (start.parse()?, end.parse()?)
+            } else {
+                let value = range.parse()?;
+                // `5/15` means every 15 starting at 5
+                (value, if part.contains('/') { max } else { value })
+            };
+            if start < min || end > max || start > end {
+                return Err(anyhow!("Value out of range {min}-{max} in {field}"));
+            }
+            for value in (start..=end).step_by(step as usize) {
+                allowed[value as usize] = true;
+            }
+        }
+        Ok(allowed)
+    }
+
+    fn day_matches(&self, date: &NaiveDateTime) -> bool {
+        let day = self.days[date.day() as usize];
+        let weekday = self.weekdays[date.weekday().num_days_from_sunday() as usize];
+        // Classic cron: when both day fields are restricted either of them may match
+        let day_matches = match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (false, true) => day,
+            (true, false) => weekday,
+            (false, false) => day || weekday,
+        };
+        day_matches && self.months[date.month() as usize]
+    }
+
+    /// First local time strictly after `after` matching the expression, in the time zone of `after`
+    pub fn next_after<Tz: TimeZone>(&self, after: DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let zone = after.timezone();
+        let mut candidate =
+            after.naive_local().with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        // Searching minute by minute with whole day/hour skips, 5 years covers Feb 29 schedules
+        let limit = candidate + Duration::days(366 * 5);
+        while candidate < limit {
+            if !self.day_matches(&candidate) {
+                candidate = candidate.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+                continue;
+            }
+            if !self.hours[candidate.hour() as usize] {
+                candidate = candidate.with_minute(0)? + Duration::hours(1);
+                continue;
+            }
+            if self.minutes[candidate.minute() as usize] {
+                // Skips times that don't exist because of a DST jump
+                if let Some(time) = zone.from_local_datetime(&candidate).earliest() {
+                    return Some(time);
+                }
+            }
+            candidate += Duration::minutes(1);
+        }
+        None
+    }
+}
+
+impl FromStr for CronExpr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields.as_slice() else {
+            return Err(anyhow!("Cron expression must have 5 fields, got {s}"));
+        };
+        let mut weekdays = Self::parse_field(weekday, 0, 7)?;
+        if weekdays[7] {
+            weekdays[0] = true;
+        }
+        Ok(Self {
+            minutes: Self::parse_field(minute, 0, 59)?,
+            hours: Self::parse_field(hour, 0, 23)?,
+            days: Self::parse_field(day, 1, 31)?,
+            months: Self::parse_field(month, 1, 12)?,
+            weekdays,
+            // like Vixie cron `*/2` still counts as unrestricted for the day-or-weekday rule
+            any_day: day.starts_with('*'),
+            any_weekday: weekday.starts_with('*'),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, Utc};
+    use chrono_tz::Europe::Berlin;
+
+    fn at(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> DateTime<Utc> {
+        Utc.from_utc_datetime(
+            &NaiveDate::from_ymd_opt(y, mo, d)
+                .unwrap()
+                .and_hms_opt(h, mi, 0)
+                .unwrap(),
+        )
+    }
+
+    fn next(expr: &str, after: DateTime<Utc>) -> DateTime<Utc> {
+        CronExpr::from_str(expr).unwrap().next_after(after).unwrap()
+    }
+
+    #[test]
+    fn lists_ranges_and_steps() {
+        let expr = CronExpr::from_str("0,15,30-32 */6 * * *").unwrap();
+        let minutes: Vec<u32> = (0..60).filter(|m| expr.minutes[*m as usize]).collect();
+        assert_eq!(minutes, vec![0, 15, 30, 31, 32]);
+        let hours: Vec<u32> = (0..24).filter(|h| expr.hours[*h as usize]).collect();
+        assert_eq!(hours, vec![0, 6, 12, 18]);
+
+        let expr = CronExpr::from_str("10-20/5 * * * *").unwrap();
+        let minutes: Vec<u32> = (0..60).filter(|m| expr.minutes[*m as usize]).collect();
+        assert_eq!(minutes, vec![10, 15, 20]);
+    }
+
+    #[test]
+    fn value_with_step_runs_to_the_end() {
+        let expr = CronExpr::from_str("5/15 * * * *").unwrap();
+        let minutes: Vec<u32> = (0..60).filter(|m| expr.minutes[*m as usize]).collect();
+        assert_eq!(minutes, vec![5, 20, 35, 50]);
+    }
+
+    #[test]
+    fn rejects_invalid_fields() {
+        for expr in [
+            "* * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * * 13 *",
+            "* * * * 8",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "a * * * *",
+        ] {
+            assert!(CronExpr::from_str(expr).is_err(), "{expr} should fail");
+        }
+    }
+
+    #[test]
+    fn sunday_is_zero_and_seven() {
+        // 2026-10-18 is a Sunday
+        let saturday = at(2026, 10, 17, 12, 0);
+        assert_eq!(next("0 9 * * 0", saturday), at(2026, 10, 18, 9, 0));
+        assert_eq!(next("0 9 * * 7", saturday), at(2026, 10, 18, 9, 0));
+    }
+
+    #[test]
+    fn day_or_weekday_when_both_are_restricted() {
+        // the 20th or any Monday, 2026-10-19 is a Monday
+        let after = at(2026, 10, 17, 12, 0);
+        assert_eq!(next("0 0 20 * 1", after), at(2026, 10, 19, 0, 0));
+        assert_eq!(
+            next("0 0 20 * 1", at(2026, 10, 19, 0, 0)),
+            at(2026, 10, 20, 0, 0)
+        );
+    }
+
+    #[test]
+    fn starred_step_counts_as_unrestricted() {
+        // only the weekday restricts, Mondays on every day-of-month
+        let after = at(2026, 10, 17, 12, 0);
+        assert_eq!(next("0 0 */1 * 1", after), at(2026, 10, 19, 0, 0));
+        assert_eq!(next("0 0 1 * */1", after), at(2026, 11, 1, 0, 0));
+    }
+
+    #[test]
+    fn rolls_over_month_and_year() {
+        assert_eq!(
+            next("0 0 1 * *", at(2026, 1, 31, 23, 59)),
+            at(2026, 2, 1, 0, 0)
+        );
+        assert_eq!(
+            next("30 8 1 1 *", at(2026, 6, 1, 0, 0)),
+            at(2027, 1, 1, 8, 30)
+        );
+        assert_eq!(
+            next("0 0 29 2 *", at(2026, 3, 1, 0, 0)),
+            at(2028, 2, 29, 0, 0)
+        );
+    }
+
+    #[test]
+    fn is_strictly_after() {
+        assert_eq!(
+            next("0 * * * *", at(2026, 5, 5, 10, 0)),
+            at(2026, 5, 5, 11, 0)
+        );
+        assert_eq!(
+            next("* * * * *", at(2026, 5, 5, 10, 0)),
+            at(2026, 5, 5, 10, 1)
+        );
+    }
+
+    #[test]
+    fn skips_times_missing_on_dst_start() {
+        // 2026-03-29 02:00 jumps to 03:00 in Berlin
+        let after = Berlin.with_ymd_and_hms(2026, 3, 28, 12, 0, 0).unwrap();
+        let next = CronExpr::from_str("30 2 * * *")
+            .unwrap()
+            .next_after(after)
+            .unwrap();
+        assert_eq!(
+            next,
+            Berlin.with_ymd_and_hms(2026, 3, 30, 2, 30, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn runs_once_when_dst_ends() {
+        // 2026-10-25 03:00 goes back to 02:00 in Berlin, 02:30 happens twice
+        let expr = CronExpr::from_str("30 2 * * *").unwrap();
+        let after = Berlin.with_ymd_and_hms(2026, 10, 24, 12, 0, 0).unwrap();
+        let first = expr.next_after(after).unwrap();
+        assert_eq!(first.naive_local(), at(2026, 10, 25, 2, 30).naive_utc());
+        assert_eq!(first.offset().to_string(), "CEST");
+        let second = expr.next_after(first).unwrap();
+        assert_eq!(second.naive_local(), at(2026, 10, 26, 2, 30).naive_utc());
+    }
+}
@@ -0,0 +1,206 @@
+pub mod cron;
+
+use crate::jobs::history::{Actor, begin_as};
+use crate::pg::pg::PgConnect;
+use crate::schedule::cron::CronExpr;
+use crate::twitch::chat_message::{ChatMessage, MessageCommands, MessageStatus};
+use anyhow::anyhow;
+use chrono::{DateTime, Duration, Local, NaiveTime, TimeZone, Utc};
+use serde::Serialize;
+use std::str::FromStr;
+use tokio_postgres::Row;
+use tracing::{error, info};
+use uuid::Uuid;
+
+/// Recurring job, materialized into `chat_messages` by the worker whenever it is due
+#[derive(Debug, Serialize)]
+pub struct Schedule {
+    id: Uuid,
+    name: String,
+    cron: String,
+    username: String,
+    message: String,
+    enabled: bool,
+    last_run_at: Option<DateTime<Utc>>,
+    next_run_at: DateTime<Utc>,
+}
+
+/// Turns an admin written message into a job, e.g. `!SET hacker` or `!ADMIN text for the overlay`
+pub fn parse_scheduled_message(username: &str, message: &str) -> anyhow::Result<ChatMessage> {
+    let (command, text) = message
+        .trim()
+        .split_once(char::is_whitespace)
+        .ok_or(anyhow!("Message must look like `!COMMAND text`"))?;
+    let (command, username) = match command.to_uppercase().as_str() {
+        "!ADMIN" => (MessageCommands::AdminMessage, "admin"),
+        "!COMPANION" => (MessageCommands::CompanionMessage, "companion"),
+        _ => {
+            return ChatMessage::from_raw_message(message.trim().to_string(), username.to_string());
+        }
+    };
+    Ok(ChatMessage::new(
+        None,
+        text.trim().to_string(),
+        command,
+        username.to_string(),
+        MessageStatus::Awaiting,
+    ))
+}
+
+/// Accepts RFC 3339 timestamps or a local `HH:MM`, which means the next time the clock shows it
+pub fn parse_run_at(run_at: &str) -> anyhow::Result<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(run_at) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    let time = NaiveTime::parse_from_str(run_at.trim(), "%H:%M")
+        .map_err(|_| anyhow!("run_at must be RFC 3339 or HH:MM, got {run_at}"))?;
+    let now = Local::now();
+    let mut date = now.date_naive();
+    if time <= now.time() {
+        date += Duration::days(1);
+    }
+    let local = Local
+        .from_local_datetime(&date.and_time(time))
+        .earliest()
+        .ok_or(anyhow!("{run_at} does not exist in local time"))?;
+    Ok(local.with_timezone(&Utc))
+}
+
+impl Schedule {
+    pub async fn create(
+        name: &str,
+        cron: &str,
+        username: &str,
+        message: &str,
+    ) -> anyhow::Result<Self> {
+        let expr = CronExpr::from_str(cron)?;
+        parse_scheduled_message(username, message)?;
+        let next_run_at = expr
+            .next_after(Local::now())
+            .ok_or(anyhow!("Cron expression {cron} never fires"))?
+            .with_timezone(&Utc);
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "INSERT INTO schedules (name, cron, username, message, next_run_at) \
+            VALUES ($1, $2, $3, $4, $5) RETURNING *";
+        let row = client
+            .query_one(query, &[&name, &cron, &username, &message, &next_run_at])
+            .await?;
+        Self::from_row(&row)
+    }
+
+    pub async fn get_all() -> anyhow::Result<Vec<Self>> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let rows = client
+            .query("SELECT * FROM schedules ORDER BY next_run_at asc", &[])
+            .await?;
+        rows.iter().map(Self::from_row).collect()
+    }
+
+    /// Enabling moves the schedule to its next occurrence, the one missed while it was off
+    /// would fire right away
+    pub async fn set_enabled(id: &str, enabled: bool) -> anyhow::Result<u64> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let id = Uuid::from_str(id)?;
+        let Some(row) = client
+            .query_opt("SELECT cron FROM schedules WHERE id = $1", &[&id])
+            .await?
+        else {
+            return Ok(0);
+        };
+        let cron: String = row.try_get("cron")?;
+        let next_run_at = if enabled {
+            let next = CronExpr::from_str(&cron)?
+                .next_after(Local::now())
+                .ok_or(anyhow!("Cron expression {cron} never fires"))?;
+            Some(next.with_timezone(&Utc))
+        } else {
+            None
+        };
+        let query = "UPDATE schedules SET enabled = $1, next_run_at = coalesce($3, next_run_at) \
+            WHERE id = $2";
+        Ok(client
+            .execute(query, &[&enabled, &id, &next_run_at])
+            .await?)
+    }
+
+    pub async fn delete(id: &str) -> anyhow::Result<u64> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let id = Uuid::from_str(id)?;
+        Ok(client
+            .execute("DELETE FROM schedules WHERE id = $1", &[&id])
+            .await?)
+    }
+
+    /// Queues one job per due schedule and moves it to the next occurrence.
+    /// Occurrences missed while no worker was running are collapsed into a single job.
+    pub async fn materialize_due() -> anyhow::Result<usize> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let mut client = pool.get().await?;
+        let tx = begin_as(&mut client, &Actor::System, Some("scheduled")).await?;
+        let query =
+            "SELECT * FROM schedules WHERE enabled AND next_run_at <= now() FOR UPDATE SKIP LOCKED";
+        let due = tx.query(query, &[]).await?;
+        let mut queued = 0;
+        for row in due {
+            let schedule = Self::from_row(&row)?;
+            let next_run_at = match CronExpr::from_str(&schedule.cron)
+                .map(|expr| expr.next_after(Local::now()))
+            {
+                Ok(Some(next)) => next.with_timezone(&Utc),
+                Ok(None) | Err(_) => {
+                    error!(
+                        "Disabling schedule {} with broken cron {}",
+                        schedule.name, schedule.cron
+                    );
+                    tx.execute(
+                        "UPDATE schedules SET enabled = false WHERE id = $1",
+                        &[&schedule.id],
+                    )
+                    .await?;
+                    continue;
+                }
+            };
+            match parse_scheduled_message(&schedule.username, &schedule.message) {
+                Ok(message) => {
+                    let query = "INSERT INTO chat_messages ( username, text, command, status, run_at ) VALUES ($1, $2, $3, $4, $5)";
+                    tx.execute(
+                        query,
+                        &[
+                            &message.username,
+                            &message.text,
+                            &message.command.to_string(),
+                            &MessageStatus::Awaiting.to_string(),
+                            &schedule.next_run_at,
+                        ],
+                    )
+                    .await?;
+                    queued += 1;
+                    info!("Queued scheduled job {}", schedule.name);
+                }
+                Err(e) => error!("Schedule {} has invalid message {:?}", schedule.name, e),
+            }
+            let query =
+                "UPDATE schedules SET last_run_at = next_run_at, next_run_at = $1 WHERE id = $2";
+            tx.execute(query, &[&next_run_at, &schedule.id]).await?;
+        }
+        tx.commit().await?;
+        Ok(queued)
+    }
+
+    fn from_row(row: &Row) -> anyhow::Result<Self> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            name: row.try_get("name")?,
+            cron: row.try_get("cron")?,
+            username: row.try_get("username")?,
+            message: row.try_get("message")?,
+            enabled: row.try_get("enabled")?,
+            last_run_at: row.try_get("last_run_at")?,
+            next_run_at: row.try_get("next_run_at")?,
+        })
+    }
+}
@@ -0,0 +1,24 @@
+use tokio::signal::unix::{SignalKind, signal};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info};
+
+/// Returns a token cancelled on the first SIGINT or SIGTERM, every long running task watches it
+pub fn listen_for_shutdown() -> CancellationToken {
+    let token = CancellationToken::new();
+    let cancel = token.clone();
+    tokio::spawn(async move {
+        let mut terminate = match signal(SignalKind::terminate()) {
+            Ok(terminate) => terminate,
+            Err(e) => {
+                error!("Error installing SIGTERM handler {:?}", e);
+                return;
+            }
+        };
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => info!("Received SIGINT, shutting down"),
+            _ = terminate.recv() => info!("Received SIGTERM, shutting down"),
+        }
+        cancel.cancel();
+    });
+    token
+}
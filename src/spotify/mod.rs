@@ -1,6 +1,7 @@
 use crate::terminal::send_to_terminal;
+use anyhow::anyhow;
 use reqwest::Client;
-use rspotify::clients::OAuthClient;
+use rspotify::clients::{BaseClient, OAuthClient};
 use rspotify::model::{PlayableId, TrackId};
 use rspotify::{AuthCodeSpotify, Config, Credentials, OAuth, scopes};
 use tracing::error;
@@ -11,7 +12,8 @@ pub fn extract_track_id(url: &str) -> Option<&str> {
         .and_then(|s| s.split('?').next())
 }
 
-pub async fn get_spotify_auth_token(url: &str) -> anyhow::Result<()> {
+/// Queues the track on the active device and returns its display name
+pub async fn get_spotify_auth_token(url: &str) -> anyhow::Result<String> {
     match extract_track_id(url) {
         Some(uri) => {
             let creds = Credentials::from_env().unwrap();
@@ -40,18 +42,22 @@ pub async fn get_spotify_auth_token(url: &str) -> anyhow::Result<()> {
             // Replace with any valid track URI:
             let track_uri = format!("spotify:track:{}", uri);
             let track_id = TrackId::from_uri(&track_uri).unwrap();
+            let track = spotify.track(track_id.clone(), None).await?;
 
             // Enqueue it on your currently active device:
             spotify
                 .add_item_to_queue(PlayableId::Track(track_id), None)
                 .await
                 .expect("failed to add to queue");
+
+            let artists: Vec<&str> = track.artists.iter().map(|a| a.name.as_str()).collect();
+            Ok(format!("{} - {}", artists.join(", "), track.name))
         }
         None => {
             error!("Incorrect url {:?}", url);
+            Err(anyhow!("Incorrect spotify url {url}"))
         }
     }
-    Ok(())
 }
 
 pub async fn open_track(url: &str) -> anyhow::Result<()> {
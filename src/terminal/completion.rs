@@ -0,0 +1,237 @@
+use regex::Regex;
+use std::env;
+use std::time::{Duration, Instant};
+
+/// Default patterns match the Gemini CLI input box and its tool confirmation dialog
+const DEFAULT_READY_PATTERN: &str = r"(?m)^\s*(│\s*)?>\s+(Type your message.*)?(│)?\s*$";
+const DEFAULT_BUSY_PATTERN: &str = r"(?i)esc to cancel";
+const DEFAULT_APPROVAL_PATTERN: &str =
+    r"(?i)(allow execution\?|apply this change\?|do you want to proceed\?|\(y/n\))";
+
+/// What to do when the agent asks for confirmation, `AGENT_APPROVAL_POLICY`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApprovalPolicy {
+    /// Sends `AGENT_APPROVE_KEYS`, default `Enter` (first option, allow once)
+    Approve,
+    /// Sends `AGENT_DENY_KEYS`, default `Escape`
+    Deny,
+    /// Leaves the dialog to the streamer
+    Manual,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PaneState {
+    Working,
+    /// The agent waits for the next prompt, either the ready pattern matched or the pane was quiet
+    Idle,
+    /// A confirmation dialog is open
+    Approval,
+}
+
+/// Decides from consecutive pane captures whether the agent is done with a prompt
+pub struct CompletionDetector {
+    ready: Regex,
+    busy: Regex,
+    approval: Regex,
+    quiet_for: Duration,
+    pub policy: ApprovalPolicy,
+    pub approve_keys: Vec<String>,
+    pub deny_keys: Vec<String>,
+    last_pane: String,
+    last_change: Instant,
+    changed: bool,
+    /// The submitted prompt with collapsed whitespace, its echo in the pane is not matched
+    prompt: String,
+    /// The approval in the current capture was answered, set until the pane changes
+    answered: bool,
+}
+
+impl CompletionDetector {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let ready = env::var("AGENT_READY_PATTERN").unwrap_or(DEFAULT_READY_PATTERN.to_string());
+        let busy = env::var("AGENT_BUSY_PATTERN").unwrap_or(DEFAULT_BUSY_PATTERN.to_string());
+        let approval =
+            env::var("AGENT_APPROVAL_PATTERN").unwrap_or(DEFAULT_APPROVAL_PATTERN.to_string());
+        let quiet_secs = env::var("AGENT_QUIET_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(60);
+        let policy = match env::var("AGENT_APPROVAL_POLICY").as_deref() {
+            Ok("approve") => ApprovalPolicy::Approve,
+            Ok("manual") => ApprovalPolicy::Manual,
+            _ => ApprovalPolicy::Deny,
+        };
+        let keys = |name: &str, default: &str| -> Vec<String> {
+            env::var(name)
+                .unwrap_or(default.to_string())
+                .split_whitespace()
+                .map(String::from)
+                .collect()
+        };
+        Ok(Self {
+            ready: Regex::new(&ready)?,
+            busy: Regex::new(&busy)?,
+            approval: Regex::new(&approval)?,
+            quiet_for: Duration::from_secs(quiet_secs),
+            policy,
+            approve_keys: keys("AGENT_APPROVE_KEYS", "Enter"),
+            deny_keys: keys("AGENT_DENY_KEYS", "Escape"),
+            last_pane: String::new(),
+            last_change: Instant::now(),
+            changed: false,
+            prompt: String::new(),
+            answered: false,
+        })
+    }
+
+    /// Starts watching a new prompt, `pane` is the capture right after `prompt` was submitted
+    pub fn reset(&mut self, pane: &str, prompt: &str) {
+        self.last_pane = pane.to_string();
+        self.last_change = Instant::now();
+        self.changed = false;
+        self.prompt = collapse_whitespace(prompt);
+        self.answered = false;
+    }
+
+    /// Pane lines that are part of the echoed prompt, a viewer writing "(y/n)" must not look
+    /// like a dialog. The input box border and `>` marker around the echo are ignored.
+    fn is_prompt_echo(&self, line: &str) -> bool {
+        let line = collapse_whitespace(
+            line.trim_matches(|c: char| c.is_whitespace() || "│|>".contains(c)),
+        );
+        !line.is_empty() && self.prompt.contains(&line)
+    }
+
+    /// An `Approval` is reported once per capture, until the pane changes the dialog counts as
+    /// answered and the agent as working
+    pub fn observe(&mut self, pane: &str, now: Instant) -> PaneState {
+        if pane != self.last_pane {
+            self.last_pane = pane.to_string();
+            self.last_change = now;
+            self.changed = true;
+            self.answered = false;
+        }
+        if self.answered {
+            return PaneState::Working;
+        }
+        // only the bottom of the pane is the live part, older dialogs stay in the scrollback
+        let tail = pane
+            .lines()
+            .rev()
+            .filter(|line| !line.trim().is_empty() && !self.is_prompt_echo(line))
+            .take(15)
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+            .collect::<Vec<_>>()
+            .join("\n");
+        if self.approval.is_match(&tail) {
+            self.answered = true;
+            return PaneState::Approval;
+        }
+        if self.busy.is_match(&tail) {
+            return PaneState::Working;
+        }
+        // until the agent reacts the ready prompt is the one the job was submitted into
+        if self.changed && self.ready.is_match(&tail) {
+            return PaneState::Idle;
+        }
+        if now.duration_since(self.last_change) >= self.quiet_for {
+            return PaneState::Idle;
+        }
+        PaneState::Working
+    }
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROMPT: &str = "make a snake game";
+
+    fn detector(pane: &str) -> CompletionDetector {
+        let mut detector = CompletionDetector::from_env().unwrap();
+        detector.reset(pane, PROMPT);
+        detector
+    }
+
+    fn submitted(prompt: &str) -> String {
+        format!("Gemini CLI\n│ > {prompt} │\n")
+    }
+
+    #[test]
+    fn ready_prompt_after_a_change_is_idle() {
+        let start = Instant::now();
+        let mut detector = detector(&submitted(PROMPT));
+        let pane = format!(
+            "{}Here is your game\n│ > Type your message │\n",
+            submitted(PROMPT)
+        );
+        assert_eq!(detector.observe(&pane, start), PaneState::Idle);
+    }
+
+    #[test]
+    fn ready_prompt_without_a_change_is_working() {
+        let start = Instant::now();
+        let pane = format!("{}│ > │\n", submitted(PROMPT));
+        let mut detector = detector(&pane);
+        assert_eq!(detector.observe(&pane, start), PaneState::Working);
+    }
+
+    #[test]
+    fn busy_indicator_is_working() {
+        let start = Instant::now();
+        let mut detector = detector(&submitted(PROMPT));
+        let pane = format!(
+            "{}⠋ Thinking (esc to cancel, 3s)\n│ > │\n",
+            submitted(PROMPT)
+        );
+        assert_eq!(detector.observe(&pane, start), PaneState::Working);
+    }
+
+    #[test]
+    fn quiet_pane_is_idle() {
+        let start = Instant::now();
+        let pane = submitted(PROMPT);
+        let mut detector = detector(&pane);
+        assert_eq!(detector.observe(&pane, start), PaneState::Working);
+        let later = start + Duration::from_secs(3600);
+        assert_eq!(detector.observe(&pane, later), PaneState::Idle);
+    }
+
+    #[test]
+    fn dialog_is_approval_once_per_capture() {
+        let start = Instant::now();
+        let mut detector = detector(&submitted(PROMPT));
+        let dialog = format!("{}Allow execution? (y/n)\n", submitted(PROMPT));
+        assert_eq!(detector.observe(&dialog, start), PaneState::Approval);
+        assert_eq!(detector.observe(&dialog, start), PaneState::Working);
+        let next = format!("{dialog}Apply this change?\n");
+        assert_eq!(detector.observe(&next, start), PaneState::Approval);
+    }
+
+    #[test]
+    fn prompt_asking_for_confirmation_is_no_dialog() {
+        let start = Instant::now();
+        let prompt = "ask me do you want to proceed? (y/n) before deleting";
+        let mut detector = CompletionDetector::from_env().unwrap();
+        let pane = submitted(prompt);
+        detector.reset(&pane, prompt);
+        let working = format!("{pane}Reading files\n");
+        assert_eq!(detector.observe(&working, start), PaneState::Working);
+    }
+
+    #[test]
+    fn wrapped_prompt_echo_is_ignored() {
+        let start = Instant::now();
+        let prompt = "write a script and ask (y/n) before running it";
+        let mut detector = CompletionDetector::from_env().unwrap();
+        detector.reset("", prompt);
+        let pane = "│ > write a script and ask │\n│   (y/n) before running it │\nworking\n";
+        assert_eq!(detector.observe(pane, start), PaneState::Working);
+    }
+}
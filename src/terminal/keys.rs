@@ -0,0 +1,159 @@
+/// Longest text passed to a single `send-keys -l`, long prompts are split on char boundaries
+pub const CHUNK_CHARS: usize = 200;
+
+/// One `tmux send-keys` call of a literal text
+#[derive(Debug, PartialEq)]
+pub enum Keystrokes {
+    /// Sent with `-l`, so key names like `C-c` or `Enter` are typed as text
+    Literal(String),
+    /// tmux treats a trailing `;` of an argument as a command separator even with `-l`,
+    /// so semicolons at the end of a chunk are sent as hex
+    Semicolons(usize),
+}
+
+/// Drops control characters, escape sequences lose their ESC and become plain text.
+/// Line breaks are normalized to `\n`, tabs become spaces.
+pub fn sanitize(text: &str) -> String {
+    text.replace("\r\n", "\n")
+        .replace('\r', "\n")
+        .replace('\t', " ")
+        .chars()
+        .filter(|c| *c == '\n' || !c.is_control())
+        .filter(|c| !is_invisible_format(*c))
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Bidi overrides and zero width characters that make the typed prompt differ from what mods saw
+fn is_invisible_format(c: char) -> bool {
+    matches!(
+        c,
+        '\u{200B}'..='\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}' | '\u{FEFF}'
+    )
+}
+
+/// Splits a single line of sanitized text into `send-keys` calls
+pub fn plan(line: &str, chunk_chars: usize) -> Vec<Keystrokes> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut keystrokes = Vec::new();
+    for chunk in chars.chunks(chunk_chars.max(1)) {
+        let chunk: String = chunk.iter().collect();
+        let text = chunk.trim_end_matches(';');
+        let semicolons = chunk.len() - text.len();
+        if !text.is_empty() {
+            keystrokes.push(Keystrokes::Literal(text.to_string()));
+        }
+        if semicolons > 0 {
+            keystrokes.push(Keystrokes::Semicolons(semicolons));
+        }
+    }
+    keystrokes
+}
+
+/// Arguments following `send-keys -t <target>`
+pub fn args(keystrokes: &Keystrokes) -> Vec<String> {
+    match keystrokes {
+        // `--` keeps a chunk starting with `-` from being read as a flag
+        Keystrokes::Literal(text) => vec!["-l".to_string(), "--".to_string(), text.clone()],
+        Keystrokes::Semicolons(count) => {
+            let mut args = vec!["-H".to_string()];
+            args.extend(std::iter::repeat_n("3b".to_string(), *count));
+            args
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(text: &str) -> String {
+        plan(&sanitize(text), CHUNK_CHARS)
+            .iter()
+            .map(|k| match k {
+                Keystrokes::Literal(text) => text.clone(),
+                Keystrokes::Semicolons(count) => ";".repeat(*count),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn key_names_are_sent_literally() {
+        for text in ["C-c", "Enter", "Escape", "C-d", "M-x", "F12", "BSpace"] {
+            let plan = plan(&sanitize(text), CHUNK_CHARS);
+            assert_eq!(plan, vec![Keystrokes::Literal(text.to_string())]);
+            assert_eq!(args(&plan[0])[..2], ["-l", "--"]);
+        }
+    }
+
+    #[test]
+    fn flags_are_not_parsed() {
+        let plan = plan("-t other:0 -X cancel", CHUNK_CHARS);
+        assert_eq!(args(&plan[0]), vec!["-l", "--", "-t other:0 -X cancel"]);
+    }
+
+    #[test]
+    fn trailing_semicolons_are_sent_as_hex() {
+        assert_eq!(
+            plan("kill-server;", CHUNK_CHARS),
+            vec![
+                Keystrokes::Literal("kill-server".to_string()),
+                Keystrokes::Semicolons(1)
+            ]
+        );
+        assert_eq!(plan(";;", CHUNK_CHARS), vec![Keystrokes::Semicolons(2)]);
+        assert_eq!(args(&Keystrokes::Semicolons(2)), vec!["-H", "3b", "3b"]);
+        assert_eq!(typed("a; b \\; c;"), "a; b \\; c;");
+    }
+
+    #[test]
+    fn control_characters_are_stripped() {
+        assert_eq!(sanitize("rm\x03 -rf\x04 /\x1b[2J"), "rm -rf /[2J");
+        assert_eq!(sanitize("a\x00b\x7fc\u{9b}d"), "abcd");
+        assert_eq!(sanitize("\tindented\t"), "indented");
+    }
+
+    #[test]
+    fn invisible_characters_are_stripped() {
+        assert_eq!(sanitize("safe\u{202E}txt.exe"), "safetxt.exe");
+        assert_eq!(sanitize("a\u{200B}b\u{FEFF}"), "ab");
+    }
+
+    #[test]
+    fn line_breaks_are_normalized() {
+        assert_eq!(sanitize("one\r\ntwo\rthree\n"), "one\ntwo\nthree");
+    }
+
+    #[test]
+    fn long_prompts_are_chunked_on_char_boundaries() {
+        let text = "привет мир ".repeat(50);
+        let text = sanitize(&text);
+        let plan = plan(&text, 16);
+        assert!(plan.len() > 1);
+        for keys in &plan {
+            if let Keystrokes::Literal(chunk) = keys {
+                assert!(chunk.chars().count() <= 16);
+            }
+        }
+        assert_eq!(typed(&text), text);
+    }
+
+    #[test]
+    fn semicolon_at_chunk_boundary() {
+        let plan = plan("abc;def", 4);
+        assert_eq!(
+            plan,
+            vec![
+                Keystrokes::Literal("abc".to_string()),
+                Keystrokes::Semicolons(1),
+                Keystrokes::Literal("def".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_input_sends_nothing() {
+        assert!(plan(&sanitize("\x03\x1b\u{200B}  "), CHUNK_CHARS).is_empty());
+    }
+}
@@ -1,86 +1,161 @@
+pub mod completion;
+pub mod keys;
+pub mod session;
+
+use crate::redact::redact;
+use crate::terminal::session::target;
 use regex::Regex;
 use std::env;
 use std::io::{self, Write};
+use std::process::Stdio;
 use std::time::Duration;
-use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::process::Command;
 use tokio::task::JoinHandle;
-use tokio::time::{interval, sleep};
-use tracing::info;
+use tokio::time::sleep;
+use tracing::{error, info};
 
 pub const TMUX_CMD: &str = "tmux";
+const PASTE_BUFFER: &str = "agent-prompt";
+
+/// `tmux send-keys` aimed at the configured agent pane
+fn send_keys_to_target() -> Command {
+    let mut command = Command::new(TMUX_CMD);
+    command.args(["send-keys", "-t", &target()]);
+    command
+}
 
 pub async fn send_to_terminal(command: &str) -> io::Result<()> {
-    let status = Command::new(TMUX_CMD)
-        .args(&["send-keys", command, "Enter"])
-        .status()
-        .await?;
-    if status.success() {
-        println!("\x1b[94m[SENT TO TMUX]:\x1b[0m {}", command);
-    } else {
-        eprintln!("\x1b[91m[ERROR]: tmux exited with {}\x1b[0m", status);
+    send_keystrokes_without_enter(command).await?;
+    send_enter().await
+}
+
+/// Types chat text into the agent pane. Text is sanitized and always sent literally,
+/// multi-line text is pasted so line breaks don't submit a partial prompt.
+pub async fn send_keystrokes_without_enter(text: &str) -> io::Result<()> {
+    let text = keys::sanitize(text);
+    if text.contains('\n') {
+        return paste_text(&text).await;
     }
+    for keystrokes in keys::plan(&text, keys::CHUNK_CHARS) {
+        let status = send_keys_to_target()
+            .args(keys::args(&keystrokes))
+            .status()
+            .await?;
+        if !status.success() {
+            error!("tmux exited with {status}");
+            return Err(io::Error::other(format!(
+                "tmux send-keys exited with {status}"
+            )));
+        }
+    }
+    // the worker log is often on stream, prompts are redacted like agent output
+    info!("Sent to tmux: {} (no Enter)", redact(&text, "tmux input"));
     Ok(())
 }
 
-async fn send_keystrokes_without_enter(text: &str) -> io::Result<()> {
+/// Loads the text into a tmux buffer through stdin and pastes it with bracketed paste
+async fn paste_text(text: &str) -> io::Result<()> {
+    let mut load = Command::new(TMUX_CMD)
+        .args(["load-buffer", "-b", PASTE_BUFFER, "-"])
+        .stdin(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = load.stdin.take() {
+        stdin.write_all(text.as_bytes()).await?;
+    }
+    let status = load.wait().await?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "tmux load-buffer exited with {status}"
+        )));
+    }
     let status = Command::new(TMUX_CMD)
-        .args(&["send-keys", text])
+        .args([
+            "paste-buffer",
+            "-p",
+            "-d",
+            "-b",
+            PASTE_BUFFER,
+            "-t",
+            &target(),
+        ])
         .status()
         .await?;
-    if status.success() {
-        println!("\x1b[94m[SENT TO TMUX]:\x1b[0m {} (no Enter)", text);
-    } else {
-        eprintln!("\x1b[91m[ERROR]: tmux exited with {}\x1b[0m", status);
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "tmux paste-buffer exited with {status}"
+        )));
     }
+    info!("Pasted to tmux: {} (no Enter)", redact(text, "tmux input"));
     Ok(())
 }
 
-async fn send_enter() -> io::Result<()> {
-    let status = Command::new(TMUX_CMD)
-        .args(&["send-keys", "Enter"])
-        .status()
-        .await?;
+pub async fn send_enter() -> io::Result<()> {
+    let status = send_keys_to_target().arg("Enter").status().await?;
     if status.success() {
-        println!("\x1b[94m[SENT TO TMUX]:\x1b[0m [ENTER]");
+        info!("Sent to tmux: Enter");
     } else {
-        eprintln!("\x1b[91m[ERROR]: tmux exited with {}\x1b[0m", status);
+        error!("tmux exited with {status}");
     }
     Ok(())
 }
 
-async fn send_esc() -> io::Result<()> {
-    let status = Command::new(TMUX_CMD)
-        .args(&["send-keys", "Escape"])
-        .status()
-        .await?;
+pub async fn send_esc() -> io::Result<()> {
+    let status = send_keys_to_target().arg("Escape").status().await?;
     if status.success() {
-        println!("\x1b[94m[SENT TO TMUX]:\x1b[0m [ESC]");
+        info!("Sent to tmux: Escape");
     } else {
-        eprintln!("\x1b[91m[ERROR]: tmux exited with {}\x1b[0m", status);
+        error!("tmux exited with {status}");
     }
     Ok(())
 }
 async fn send_ctrl_c() -> io::Result<()> {
-    let status = Command::new(TMUX_CMD)
-        .args(&["send-keys", "C-c"])
-        .status()
-        .await?;
+    let status = send_keys_to_target().arg("C-c").status().await?;
     if status.success() {
-        println!("\x1b[94m[SENT TO TMUX]:\x1b[0m [CTRL-C]");
+        info!("Sent to tmux: C-c");
     } else {
-        eprintln!("\x1b[91m[ERROR]: tmux exited with {}\x1b[0m", status);
+        error!("tmux exited with {status}");
     }
     Ok(())
 }
 
+/// Sends tmux key names like `Enter` or `Escape`, never use it for chat text
+pub async fn send_keys(keys: &[String]) -> io::Result<()> {
+    let status = send_keys_to_target().args(keys).status().await?;
+    if status.success() {
+        info!("Sent to tmux: {}", keys.join(" "));
+    } else {
+        error!("tmux exited with {status}");
+    }
+    Ok(())
+}
+
+/// Text of the pane including its scrollback, wrapped lines joined
+pub async fn capture_pane() -> io::Result<String> {
+    let output = Command::new(TMUX_CMD)
+        .args(["capture-pane", "-p", "-J", "-S", "-", "-t", &target()])
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "tmux capture-pane exited with {}",
+            output.status
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Interrupts whatever the agent is doing and clears the input line
+pub async fn reset_pane() -> io::Result<()> {
+    send_ctrl_c().await?;
+    sleep(Duration::from_millis(300)).await;
+    send_esc().await
+}
+
 pub async fn send_vscode_enable_custom_css() -> io::Result<()> {
     // Step 1: Simulate Cmd+Shift+P to open the Command Palette
     // In tmux, "C-S-p" often maps to Ctrl+Shift+P, which works in most cases
-    let open_palette = Command::new(TMUX_CMD)
-        .args(&["send-keys", "C-S-p"])
-        .status()
-        .await?;
+    let open_palette = send_keys_to_target().arg("C-S-p").status().await?;
 
     if !open_palette.success() {
         eprintln!("[ERROR] Failed to send Cmd+Shift+P");
@@ -93,10 +168,7 @@ pub async fn send_vscode_enable_custom_css() -> io::Result<()> {
     // Step 2: Type the command
     let command = "Enable Custom CSS and JS";
 
-    let type_command = Command::new(TMUX_CMD)
-        .args(&["send-keys", command])
-        .status()
-        .await?;
+    let type_command = send_keys_to_target().arg(command).status().await?;
 
     if !type_command.success() {
         eprintln!("[ERROR] Failed to type VSCode command");
@@ -104,10 +176,7 @@ pub async fn send_vscode_enable_custom_css() -> io::Result<()> {
     }
 
     // Step 3: Send Enter
-    let press_enter = Command::new(TMUX_CMD)
-        .args(&["send-keys", "Enter"])
-        .status()
-        .await?;
+    let press_enter = send_keys_to_target().arg("Enter").status().await?;
 
     if !press_enter.success() {
         eprintln!("[ERROR] Failed to press Enter");
@@ -181,69 +250,24 @@ pub async fn restart_vscode() -> io::Result<()> {
     Ok(())
 }
 
-async fn wait_for_exit_command() {
+/// Resolves once `/` is typed on stdin, the manual override for a stuck job.
+/// Never resolves when stdin is closed, e.g. when running as a service.
+pub async fn wait_for_manual_exit() {
     let stdin = tokio::io::stdin();
     let mut reader = BufReader::new(stdin).lines();
-    let mut ticker = interval(Duration::from_secs(3));
-
     loop {
-        tokio::select! {
-            _ = ticker.tick() => {
-                // ⏎ Always send enter every tick
-                if let Err(e) = send_enter().await {
-                    eprintln!("Error sending enter: {:?}", e);
-                } else {
-                    info!("Tick: sent enter");
+        match reader.next_line().await {
+            Ok(Some(input)) => {
+                if input.trim() == "/" {
+                    info!("Received /, exiting...");
+                    return;
                 }
             }
-
-            line = reader.next_line() => {
-                match line {
-                    Ok(Some(input)) => {
-                        if input.trim() == "/" {
-                            info!("Received /, exiting...");
-                            break;
-                        }
-                    }
-                    Ok(None) => break, // EOF
-                    Err(e) => {
-                        eprintln!("Error reading line: {:?}", e);
-                        break;
-                    }
-                }
+            Ok(None) => std::future::pending::<()>().await,
+            Err(e) => {
+                eprintln!("Error reading line: {:?}", e);
+                std::future::pending::<()>().await
             }
         }
     }
 }
-
-pub async fn say_send(sender: &str, prompt: &str) -> anyhow::Result<()> {
-    let prefix = "Сообщение от ";
-    let prompt = format!("{prefix} {sender} {prompt}");
-    Command::new("say")
-        .arg("-v")
-        .arg("Milena")
-        .arg("-r")
-        .arg("125")
-        .arg(prompt)
-        .status()
-        .await?;
-    Ok(())
-}
-
-pub async fn test_send_to_terminal(sender: &str, prompt: &str) -> io::Result<()> {
-    println!("\nStep 1: Clearing and starting Gemini");
-    tokio::time::sleep(Duration::from_secs(2)).await;
-    say_send(sender, prompt).await.unwrap();
-
-    println!("\nStep 2.5: Pressing ESC to clear any previous input");
-    send_esc().await?;
-
-    println!("\nStep 3: Type prompt");
-    send_keystrokes_without_enter(prompt).await?;
-    tokio::time::sleep(Duration::from_secs(1)).await;
-    send_enter().await?;
-    println!("\nStep 4: Type `/` at any time to cancel...");
-    wait_for_exit_command().await;
-    info!("Exiting terminal task");
-    Ok(())
-}
@@ -0,0 +1,157 @@
+use crate::terminal::TMUX_CMD;
+use crate::workspace::sandbox;
+use crate::workspace::workspace_dir;
+use anyhow::anyhow;
+use std::env;
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::{info, warn};
+
+/// Where the agent lives, `TMUX_SESSION`, `TMUX_WINDOW` and `TMUX_PANE`
+#[derive(Debug, Clone)]
+pub struct TmuxTarget {
+    pub session: String,
+    pub window: String,
+    pub pane: String,
+}
+
+impl TmuxTarget {
+    pub fn from_env() -> Self {
+        Self {
+            session: env::var("TMUX_SESSION").unwrap_or("agent".to_string()),
+            window: env::var("TMUX_WINDOW").unwrap_or("0".to_string()),
+            pane: env::var("TMUX_PANE").unwrap_or("0".to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for TmuxTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}.{}", self.session, self.window, self.pane)
+    }
+}
+
+/// `-t` argument for every tmux call
+pub fn target() -> String {
+    TmuxTarget::from_env().to_string()
+}
+
+/// Command the agent pane is started with, `AGENT_TMUX_COMMAND`
+fn agent_command() -> String {
+    env::var("AGENT_TMUX_COMMAND").unwrap_or("gemini".to_string())
+}
+
+/// `agent_command` as started in the pane, wrapped by the sandbox if it is on
+fn pane_command() -> anyhow::Result<String> {
+    sandbox::wrap_shell_command(&agent_command())
+}
+
+/// Process expected in the pane, `AGENT_PROCESS` or the program of `AGENT_TMUX_COMMAND` as
+/// wrapped by the sandbox. Node based CLIs show up as `node`, so set it explicitly for those.
+fn expected_process() -> String {
+    env::var("AGENT_PROCESS").unwrap_or_else(|_| {
+        let command = agent_command();
+        sandbox::wrapped_program(command.split_whitespace().next().unwrap_or_default())
+    })
+}
+
+#[derive(Debug)]
+pub struct PaneHealth {
+    pub target: String,
+    pub expected: String,
+    pub running: Option<String>,
+    pub healthy: bool,
+}
+
+async fn tmux(args: &[&str]) -> anyhow::Result<std::process::Output> {
+    Ok(Command::new(TMUX_CMD).args(args).output().await?)
+}
+
+/// Checks that the target pane exists and runs the agent
+pub async fn check_health() -> anyhow::Result<PaneHealth> {
+    let target = target();
+    let expected = expected_process();
+    let output = tmux(&[
+        "display-message",
+        "-p",
+        "-t",
+        &target,
+        "#{pane_current_command}",
+    ])
+    .await?;
+    let running = output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string());
+    let healthy = running.as_deref() == Some(expected.as_str());
+    Ok(PaneHealth {
+        target,
+        expected,
+        running,
+        healthy,
+    })
+}
+
+/// Gives a freshly started agent time to draw its input prompt, `AGENT_STARTUP_SECS`
+async fn wait_for_startup() {
+    let secs = env::var("AGENT_STARTUP_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(5);
+    tokio::time::sleep(Duration::from_secs(secs)).await;
+}
+
+/// Creates the session with the agent if it is missing and restarts the agent
+/// when the pane runs something else, e.g. a shell after the agent crashed
+pub async fn ensure_agent_pane() -> anyhow::Result<()> {
+    let tmux_target = TmuxTarget::from_env();
+    let command = pane_command()?;
+    let has_session = tmux(&["has-session", "-t", &tmux_target.session]).await?;
+    if !has_session.status.success() {
+        info!(
+            "Creating tmux session {} running {command}",
+            tmux_target.session
+        );
+        let dir = workspace_dir()
+            .unwrap_or(env::current_dir()?)
+            .display()
+            .to_string();
+        let created = tmux(&[
+            "new-session",
+            "-d",
+            "-s",
+            &tmux_target.session,
+            "-c",
+            &dir,
+            &command,
+        ])
+        .await?;
+        if !created.status.success() {
+            return Err(anyhow!(
+                "Failed to create tmux session {}: {}",
+                tmux_target.session,
+                String::from_utf8_lossy(&created.stderr)
+            ));
+        }
+        wait_for_startup().await;
+    }
+
+    let health = check_health().await?;
+    if health.healthy {
+        return Ok(());
+    }
+    warn!(
+        "Pane {} runs {:?} instead of {}, restarting the agent",
+        health.target, health.running, health.expected
+    );
+    let respawned = tmux(&["respawn-pane", "-k", "-t", &health.target, &command]).await?;
+    if !respawned.status.success() {
+        return Err(anyhow!(
+            "Failed to restart the agent in {}: {}",
+            health.target,
+            String::from_utf8_lossy(&respawned.stderr)
+        ));
+    }
+    wait_for_startup().await;
+    Ok(())
+}
@@ -0,0 +1,124 @@
+use crate::pg::pg::PgConnect;
+use crate::tts::{TtsEngine, VoiceSettings};
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::env;
+use std::path::PathBuf;
+use std::str::FromStr;
+use tokio::process::Command;
+use tokio_postgres::Row;
+use tracing::info;
+use uuid::Uuid;
+
+/// Directory rendered announcements are kept in, `TTS_AUDIO_DIR`
+pub fn audio_dir() -> PathBuf {
+    PathBuf::from(env::var("TTS_AUDIO_DIR").unwrap_or("audio".to_string()))
+}
+
+/// `wav` or `ogg` from `TTS_AUDIO_FORMAT`, ogg is encoded from the rendered wav with `ffmpeg`
+fn audio_format() -> String {
+    match env::var("TTS_AUDIO_FORMAT").as_deref() {
+        Ok("ogg") => "ogg".to_string(),
+        _ => "wav".to_string(),
+    }
+}
+
+/// Announcement of a job rendered to a file, played by the overlay instead of the host speaker
+#[derive(Debug, Serialize)]
+pub struct AudioClip {
+    pub message_id: Uuid,
+    pub format: String,
+    created_at: DateTime<Utc>,
+    played_at: Option<DateTime<Utc>>,
+}
+
+impl AudioClip {
+    pub fn path(&self) -> PathBuf {
+        audio_dir().join(format!("{}.{}", self.message_id, self.format))
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self.format.as_str() {
+            "ogg" => "audio/ogg",
+            _ => "audio/wav",
+        }
+    }
+
+    /// Renders the text into `<TTS_AUDIO_DIR>/<message_id>.<format>` and queues it for the overlay.
+    /// Rendering the same job again replaces the clip.
+    pub async fn render(
+        engine: &dyn TtsEngine,
+        message_id: &str,
+        text: &str,
+        voice: &VoiceSettings,
+    ) -> anyhow::Result<()> {
+        let message_id = Uuid::from_str(message_id)?;
+        let format = audio_format();
+        let dir = audio_dir();
+        tokio::fs::create_dir_all(&dir).await?;
+        let wav = dir.join(format!("{message_id}.wav"));
+        engine.render(text, voice, &wav).await?;
+        if format == "ogg" {
+            let ogg = dir.join(format!("{message_id}.ogg"));
+            let output = Command::new("ffmpeg")
+                .args(["-y", "-loglevel", "error", "-i"])
+                .arg(&wav)
+                .args(["-c:a", "libvorbis"])
+                .arg(&ogg)
+                .output()
+                .await?;
+            tokio::fs::remove_file(&wav).await?;
+            if !output.status.success() {
+                return Err(anyhow!(
+                    "ffmpeg failed: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+        }
+        info!("Rendered announcement {message_id}.{format}");
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "INSERT INTO tts_clips (message_id, format) VALUES ($1, $2) \
+            ON CONFLICT (message_id) DO UPDATE SET format = $2, created_at = now(), played_at = NULL";
+        client.execute(query, &[&message_id, &format]).await?;
+        Ok(())
+    }
+
+    pub async fn get(message_id: &str) -> anyhow::Result<Option<Self>> {
+        let message_id = Uuid::from_str(message_id)?;
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "SELECT * FROM tts_clips WHERE message_id = $1";
+        let row = client.query_opt(query, &[&message_id]).await?;
+        row.as_ref().map(Self::from_row).transpose()
+    }
+
+    /// Oldest clip the overlay hasn't played, marked as played so every clip is handed out once
+    pub async fn take_next() -> anyhow::Result<Option<Self>> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "UPDATE tts_clips SET played_at = now() WHERE message_id = \
+            (SELECT message_id FROM tts_clips WHERE played_at IS NULL ORDER BY created_at LIMIT 1 FOR UPDATE SKIP LOCKED) \
+            RETURNING *";
+        let row = client.query_opt(query, &[]).await?;
+        row.as_ref().map(Self::from_row).transpose()
+    }
+
+    /// Drops every clip the overlay hasn't played yet, returns how many
+    pub async fn skip_pending() -> anyhow::Result<u64> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "UPDATE tts_clips SET played_at = now() WHERE played_at IS NULL";
+        Ok(client.execute(query, &[]).await?)
+    }
+
+    fn from_row(row: &Row) -> anyhow::Result<Self> {
+        Ok(Self {
+            message_id: row.try_get("message_id")?,
+            format: row.try_get("format")?,
+            created_at: row.try_get("created_at")?,
+            played_at: row.try_get("played_at")?,
+        })
+    }
+}
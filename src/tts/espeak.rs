@@ -0,0 +1,60 @@
+use crate::tts::{TtsEngine, VoiceSettings, kill_process};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use std::path::Path;
+use tokio::process::Command;
+
+/// `espeak-ng`, available on most Linux distributions, voice defaults to `ru`
+pub struct EspeakEngine {}
+
+#[async_trait]
+impl TtsEngine for EspeakEngine {
+    fn name(&self) -> &'static str {
+        "espeak"
+    }
+
+    async fn speak(&self, text: &str, voice: &VoiceSettings) -> anyhow::Result<()> {
+        let status = Command::new("espeak-ng")
+            .arg("-v")
+            .arg(voice.voice.as_deref().unwrap_or("ru"))
+            .arg("-s")
+            .arg(voice.rate.to_string())
+            // amplitude 100 is the default of espeak-ng
+            .arg("-a")
+            .arg(voice.volume.clamp(0, 100).to_string())
+            .kill_on_drop(true)
+            // text after `--` is never read as an option
+            .arg("--")
+            .arg(text)
+            .status()
+            .await?;
+        if !status.success() {
+            return Err(anyhow!("espeak-ng exited with {status}"));
+        }
+        Ok(())
+    }
+
+    async fn render(&self, text: &str, voice: &VoiceSettings, path: &Path) -> anyhow::Result<()> {
+        let status = Command::new("espeak-ng")
+            .arg("-v")
+            .arg(voice.voice.as_deref().unwrap_or("ru"))
+            .arg("-s")
+            .arg(voice.rate.to_string())
+            .arg("-a")
+            .arg(voice.volume.clamp(0, 100).to_string())
+            .arg("-w")
+            .arg(path)
+            .arg("--")
+            .arg(text)
+            .status()
+            .await?;
+        if !status.success() {
+            return Err(anyhow!("espeak-ng exited with {status}"));
+        }
+        Ok(())
+    }
+
+    async fn stop(&self) -> anyhow::Result<()> {
+        kill_process("espeak-ng").await
+    }
+}
@@ -0,0 +1,55 @@
+use crate::tts::{TtsEngine, VoiceSettings, engine_timeout, kill_process, play_rendered, player};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use reqwest::Client;
+use serde_json::json;
+use std::env;
+use std::path::Path;
+
+/// Any HTTP provider taking `{"text", "voice", "rate"}` and answering with WAV audio,
+/// `TTS_HTTP_URL` and an optional `TTS_HTTP_TOKEN` sent as bearer token
+pub struct HttpEngine {
+    client: Client,
+    url: String,
+    token: Option<String>,
+}
+
+impl HttpEngine {
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(Self {
+            client: Client::builder().timeout(engine_timeout()).build()?,
+            url: env::var("TTS_HTTP_URL").map_err(|_| anyhow!("TTS_HTTP_URL is not set"))?,
+            token: env::var("TTS_HTTP_TOKEN").ok(),
+        })
+    }
+}
+
+#[async_trait]
+impl TtsEngine for HttpEngine {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    async fn speak(&self, text: &str, voice: &VoiceSettings) -> anyhow::Result<()> {
+        play_rendered(self, text, voice).await
+    }
+
+    async fn render(&self, text: &str, voice: &VoiceSettings, path: &Path) -> anyhow::Result<()> {
+        let mut request = self.client.post(&self.url).json(&json!({
+            "text": text,
+            "voice": voice.voice,
+            "rate": voice.rate,
+        }));
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await?.error_for_status()?;
+        let audio = response.bytes().await?;
+        tokio::fs::write(path, &audio).await?;
+        Ok(())
+    }
+
+    async fn stop(&self) -> anyhow::Result<()> {
+        kill_process(&player()).await
+    }
+}
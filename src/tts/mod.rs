@@ -0,0 +1,316 @@
+pub mod clip;
+pub mod espeak;
+pub mod http;
+pub mod normalize;
+pub mod piper;
+pub mod queue;
+pub mod say;
+
+use crate::tts::clip::AudioClip;
+use crate::tts::espeak::EspeakEngine;
+use crate::tts::http::HttpEngine;
+use crate::tts::piper::PiperEngine;
+use crate::tts::queue::TtsQueue;
+use crate::tts::say::SayEngine;
+use crate::twitch::chat_message::MessageCommands;
+use anyhow::anyhow;
+use async_trait::async_trait;
+use std::env;
+use std::path::Path;
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::info;
+use uuid::Uuid;
+
+#[derive(Debug, Clone)]
+pub struct VoiceSettings {
+    /// Engine specific voice, e.g. `Milena` for `say`, `ru` for `espeak-ng`, a model path for `piper`
+    pub voice: Option<String>,
+    /// Words per minute
+    pub rate: u32,
+    /// Spoken before the sender, e.g. "Сообщение от"
+    pub prefix: String,
+    /// Percent of the natural loudness, set from the TTS queue
+    pub volume: i32,
+}
+
+/// Whether messages of the command are read out, `TTS_COMMANDS` lists stored command names
+/// like `!STORE,!SET,!PLAY,!UNDO,!PROMOTE,ADMIN,COMPANION`, default only `!STORE`
+pub fn is_announced(command: &MessageCommands) -> bool {
+    announced_in(env::var("TTS_COMMANDS").ok().as_deref(), command)
+}
+
+fn announced_in(commands: Option<&str>, command: &MessageCommands) -> bool {
+    let command = command.to_string();
+    commands
+        .unwrap_or("!STORE")
+        .split(',')
+        .any(|name| name.trim().eq_ignore_ascii_case(&command))
+}
+
+/// Longest an engine may take to produce one announcement, `TTS_ENGINE_TIMEOUT_SECS` (default `60`),
+/// so a stalled provider fails the announcement instead of holding the queue until the lease runs out
+pub fn engine_timeout() -> Duration {
+    let secs = env::var("TTS_ENGINE_TIMEOUT_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(60);
+    Duration::from_secs(secs)
+}
+
+impl VoiceSettings {
+    /// `TTS_<COMMAND>_VOICE`, `TTS_<COMMAND>_RATE` and `TTS_<COMMAND>_PREFIX`, falling back to
+    /// `TTS_VOICE`, `TTS_RATE` and `TTS_PREFIX`. The command is named without `!`, e.g. `TTS_STORE_VOICE`.
+    pub fn for_command(command: &MessageCommands) -> Self {
+        Self::from_vars(command, |name| env::var(name).ok())
+    }
+
+    fn from_vars(command: &MessageCommands, var: impl Fn(&str) -> Option<String>) -> Self {
+        let key = command.to_string().trim_start_matches('!').to_string();
+        let lookup =
+            |name: &str| var(&format!("TTS_{key}_{name}")).or_else(|| var(&format!("TTS_{name}")));
+        Self {
+            voice: lookup("VOICE"),
+            rate: lookup("RATE").and_then(|v| v.parse().ok()).unwrap_or(125),
+            prefix: lookup("PREFIX").unwrap_or("Сообщение от".to_string()),
+            volume: 100,
+        }
+    }
+
+    /// Sentence that is spoken for a message
+    pub fn utterance(&self, sender: &str, text: &str) -> String {
+        format!("{} {sender} {text}", self.prefix)
+            .trim()
+            .to_string()
+    }
+}
+
+#[async_trait]
+pub trait TtsEngine: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Speaks the text and resolves once it is done
+    async fn speak(&self, text: &str, voice: &VoiceSettings) -> anyhow::Result<()>;
+
+    /// Renders the text into a WAV file instead of playing it
+    async fn render(&self, text: &str, voice: &VoiceSettings, path: &Path) -> anyhow::Result<()>;
+
+    /// Cuts off whatever is being spoken
+    async fn stop(&self) -> anyhow::Result<()>;
+}
+
+/// Picks the engine from `TTS_ENGINE`: `say`, `espeak`, `piper` or `http`.
+/// Defaults to `say` on macOS and `espeak` elsewhere.
+pub fn engine_from_env() -> anyhow::Result<Box<dyn TtsEngine>> {
+    let default = if cfg!(target_os = "macos") {
+        "say"
+    } else {
+        "espeak"
+    };
+    let engine = env::var("TTS_ENGINE").unwrap_or(default.to_string());
+    let engine: Box<dyn TtsEngine> = match engine.as_str() {
+        "say" => Box::new(SayEngine {}),
+        "espeak" => Box::new(EspeakEngine {}),
+        "piper" => Box::new(PiperEngine::from_env()?),
+        "http" => Box::new(HttpEngine::from_env()?),
+        other => return Err(anyhow!("Unknown TTS_ENGINE {other}")),
+    };
+    Ok(engine)
+}
+
+/// Where announcements end up, `TTS_OUTPUT`
+#[derive(Debug, PartialEq)]
+pub enum TtsOutput {
+    /// Played on the host speaker
+    Speaker,
+    /// Rendered into `TTS_AUDIO_DIR` and played by the overlay in the browser source
+    Files,
+}
+
+impl TtsOutput {
+    pub fn from_env() -> Self {
+        match env::var("TTS_OUTPUT").as_deref() {
+            Ok("files") => TtsOutput::Files,
+            _ => TtsOutput::Speaker,
+        }
+    }
+}
+
+/// Speaks or renders an utterance that is already redacted and normalized
+pub async fn announce(
+    message_id: &str,
+    command: &MessageCommands,
+    utterance: &str,
+    voice: &VoiceSettings,
+) -> anyhow::Result<()> {
+    let engine = engine_from_env()?;
+    info!("Announcing {command} with {}", engine.name());
+    match TtsOutput::from_env() {
+        TtsOutput::Speaker => engine.speak(utterance, voice).await,
+        TtsOutput::Files => AudioClip::render(engine.as_ref(), message_id, utterance, voice).await,
+    }
+}
+
+/// Stops what the host speaker is saying, the queue item itself is left as it is
+pub async fn stop_speaker() -> anyhow::Result<()> {
+    engine_from_env()?.stop().await
+}
+
+/// Drops every queued announcement and clips the overlay hasn't played yet. The worker speaking
+/// notices the skip and cuts off the speaker, so this works from any process.
+pub async fn stop() -> anyhow::Result<()> {
+    TtsQueue::skip_all().await?;
+    // the process handling the kill may not know the worker's `TTS_OUTPUT`
+    AudioClip::skip_pending().await?;
+    Ok(())
+}
+
+/// Player for engines producing audio files, `TTS_PLAYER`, defaults to `afplay` on macOS and `aplay` elsewhere
+fn player() -> String {
+    env::var("TTS_PLAYER").unwrap_or_else(|_| {
+        if cfg!(target_os = "macos") {
+            "afplay".to_string()
+        } else {
+            "aplay".to_string()
+        }
+    })
+}
+
+/// Volume flags of the known players, others play at full volume
+fn player_volume_args(volume: i32) -> Vec<String> {
+    let volume = volume.clamp(0, 100);
+    match player().as_str() {
+        "afplay" => vec!["-v".to_string(), format!("{:.2}", volume as f32 / 100.0)],
+        "paplay" => vec![format!("--volume={}", 65536 * volume / 100)],
+        "ffplay" => vec![
+            "-nodisp".to_string(),
+            "-autoexit".to_string(),
+            "-volume".to_string(),
+            volume.to_string(),
+        ],
+        "mpv" => vec![format!("--volume={volume}")],
+        _ => Vec::new(),
+    }
+}
+
+/// `speak` for engines that can only produce files, renders into a temp file and plays it
+async fn play_rendered(
+    engine: &dyn TtsEngine,
+    text: &str,
+    voice: &VoiceSettings,
+) -> anyhow::Result<()> {
+    let path = env::temp_dir().join(format!("tts-{}.wav", Uuid::new_v4()));
+    engine.render(text, voice, &path).await?;
+    let status = Command::new(player())
+        .args(player_volume_args(voice.volume))
+        .arg(&path)
+        .kill_on_drop(true)
+        .status()
+        .await;
+    let _ = tokio::fs::remove_file(&path).await;
+    let status = status?;
+    if !status.success() {
+        return Err(anyhow!("{} exited with {status}", player()));
+    }
+    Ok(())
+}
+
+async fn kill_process(name: &str) -> anyhow::Result<()> {
+    // pkill exits with 1 when nothing was running
+    Command::new("pkill").args(["-x", name]).status().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn settings(command: MessageCommands, vars: &[(&str, &str)]) -> VoiceSettings {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        VoiceSettings::from_vars(&command, |name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn voice_defaults() {
+        let voice = settings(MessageCommands::StoreChatMessage, &[]);
+        assert_eq!(voice.voice, None);
+        assert_eq!(voice.rate, 125);
+        assert_eq!(voice.prefix, "Сообщение от");
+        assert_eq!(voice.volume, 100);
+    }
+
+    #[test]
+    fn global_voice_applies_to_every_command() {
+        let vars = [
+            ("TTS_VOICE", "Milena"),
+            ("TTS_RATE", "180"),
+            ("TTS_PREFIX", ""),
+        ];
+        for command in [MessageCommands::StoreChatMessage, MessageCommands::SetSong] {
+            let voice = settings(command, &vars);
+            assert_eq!(voice.voice.as_deref(), Some("Milena"));
+            assert_eq!(voice.rate, 180);
+            assert_eq!(voice.prefix, "");
+        }
+    }
+
+    #[test]
+    fn command_voice_overrides_global() {
+        let vars = [
+            ("TTS_VOICE", "Milena"),
+            ("TTS_RATE", "180"),
+            ("TTS_PLAY_VOICE", "Yuri"),
+            ("TTS_ADMIN_PREFIX", "Админ"),
+        ];
+        let play = settings(MessageCommands::SetSong, &vars);
+        assert_eq!(play.voice.as_deref(), Some("Yuri"));
+        assert_eq!(play.rate, 180);
+        let admin = settings(MessageCommands::AdminMessage, &vars);
+        assert_eq!(admin.voice.as_deref(), Some("Milena"));
+        assert_eq!(admin.prefix, "Админ");
+        let store = settings(MessageCommands::StoreChatMessage, &vars);
+        assert_eq!(store.voice.as_deref(), Some("Milena"));
+        assert_eq!(store.prefix, "Сообщение от");
+    }
+
+    #[test]
+    fn invalid_rate_falls_back_to_default() {
+        let voice = settings(
+            MessageCommands::StoreChatMessage,
+            &[("TTS_STORE_RATE", "fast")],
+        );
+        assert_eq!(voice.rate, 125);
+    }
+
+    #[test]
+    fn announces_only_store_by_default() {
+        assert!(announced_in(None, &MessageCommands::StoreChatMessage));
+        assert!(!announced_in(None, &MessageCommands::SetTheme));
+        assert!(!announced_in(None, &MessageCommands::AdminMessage));
+    }
+
+    #[test]
+    fn announces_listed_commands() {
+        let commands = Some(" !store , !SET,admin");
+        assert!(announced_in(commands, &MessageCommands::StoreChatMessage));
+        assert!(announced_in(commands, &MessageCommands::SetTheme));
+        assert!(announced_in(commands, &MessageCommands::AdminMessage));
+        assert!(!announced_in(commands, &MessageCommands::SetSong));
+        assert!(!announced_in(Some(""), &MessageCommands::StoreChatMessage));
+    }
+
+    #[test]
+    fn builds_utterance() {
+        let voice = settings(MessageCommands::StoreChatMessage, &[]);
+        assert_eq!(
+            voice.utterance("alice", "привет"),
+            "Сообщение от alice привет"
+        );
+        let voice = settings(MessageCommands::StoreChatMessage, &[("TTS_PREFIX", "")]);
+        assert_eq!(voice.utterance("alice", "привет"), "alice привет");
+    }
+}
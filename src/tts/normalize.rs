@@ -0,0 +1,398 @@
+use regex::Regex;
+use std::env;
+use std::sync::LazyLock;
+
+/// Runs of the same character longer than this are cut, "ааааааа" is spoken as "ааа"
+const MAX_REPEAT: usize = 3;
+
+/// Twitch and 7TV emotes that are dropped, extended with `TTS_EMOTES`
+const EMOTES: [&str; 32] = [
+    "Kappa",
+    "PogChamp",
+    "Pog",
+    "PogU",
+    "LUL",
+    "KEKW",
+    "OMEGALUL",
+    "monkaS",
+    "monkaW",
+    "PepeHands",
+    "PepeLaugh",
+    "FeelsBadMan",
+    "FeelsGoodMan",
+    "Kreygasm",
+    "ResidentSleeper",
+    "BibleThump",
+    "4Head",
+    "EZ",
+    "Sadge",
+    "Clap",
+    "catJAM",
+    "HeyGuys",
+    "VoteYea",
+    "VoteNay",
+    "NotLikeThis",
+    "WutFace",
+    "SeemsGood",
+    "CoolCat",
+    "TriHard",
+    "DansGame",
+    "Jebaited",
+    "CoolStoryBob",
+];
+
+/// Stems of obscene words, replaced when no `TTS_PROFANITY_FILE` is given
+const PROFANITY: [&str; 13] = [
+    "хуй",
+    "хуе",
+    "хуё",
+    "пизд",
+    "ебан",
+    "ебат",
+    "ёбан",
+    "бля",
+    "сука",
+    "мудак",
+    "пидор",
+    "залуп",
+    "шлюх",
+];
+
+static URL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\b(https?://|www\.)\S+|\b[\w-]+(\.[\w-]+)*\.(com|ru|net|org|io|tv|gg|me|dev|app|xyz)\b(/\S*)?")
+        .unwrap()
+});
+static WORD: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\w+").unwrap());
+static NUMBER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(\d+)(?:[.,](\d+))?").unwrap());
+
+/// Makes chat text fit for speaking, in this order: URLs become "ссылка", emotes and emoji are
+/// removed, profanity is replaced, repeated characters are trimmed, numbers are spoken in Russian
+/// and the result is cut to `TTS_MAX_CHARS` (default `300`)
+pub fn normalize(text: &str) -> String {
+    let text = URL.replace_all(text, "ссылка");
+    let text = remove_emotes(&text);
+    let text = replace_profanity(&text);
+    let text = trim_repeats(&text);
+    let text = NUMBER.replace_all(&text, |caps: &regex::Captures| match caps.get(2) {
+        Some(fraction) => format!(
+            " {} запятая {} ",
+            verbalize(&caps[1]),
+            verbalize(fraction.as_str())
+        ),
+        None => format!(" {} ", verbalize(&caps[1])),
+    });
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let max_chars = env::var("TTS_MAX_CHARS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(300);
+    cap_length(&text, max_chars)
+}
+
+fn is_emoji(c: char) -> bool {
+    matches!(c as u32, 0x1F000..=0x1FAFF | 0x2600..=0x27BF | 0xFE0F | 0x200D)
+}
+
+fn remove_emotes(text: &str) -> String {
+    let extra: Vec<String> = env::var("TTS_EMOTES")
+        .unwrap_or_default()
+        .split(',')
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect();
+    text.split_whitespace()
+        .filter(|word| !EMOTES.contains(word) && !extra.iter().any(|e| e == word))
+        .map(|word| word.chars().filter(|c| !is_emoji(*c)).collect::<String>())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Stems from `TTS_PROFANITY_FILE`, one per line, or the built-in list
+fn profanity() -> Vec<String> {
+    match env::var("TTS_PROFANITY_FILE").map(std::fs::read_to_string) {
+        Ok(Ok(list)) => list
+            .lines()
+            .map(|line| line.trim().to_lowercase())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect(),
+        _ => PROFANITY.iter().map(|stem| stem.to_string()).collect(),
+    }
+}
+
+/// Words starting with a listed stem become `TTS_PROFANITY_REPLACEMENT` (default "пип").
+/// Letter runs are collapsed before matching, so stretched words like "сууука" are caught too.
+fn replace_profanity(text: &str) -> String {
+    let stems = profanity();
+    let replacement = env::var("TTS_PROFANITY_REPLACEMENT").unwrap_or("пип".to_string());
+    WORD.replace_all(text, |caps: &regex::Captures| {
+        let word = caps[0].to_lowercase();
+        let collapsed = collapse_runs(&word);
+        if stems
+            .iter()
+            .any(|stem| word.starts_with(stem.as_str()) || collapsed.starts_with(stem.as_str()))
+        {
+            replacement.clone()
+        } else {
+            caps[0].to_string()
+        }
+    })
+    .to_string()
+}
+
+fn collapse_runs(word: &str) -> String {
+    let mut collapsed: Vec<char> = word.chars().collect();
+    collapsed.dedup();
+    collapsed.into_iter().collect()
+}
+
+/// Cuts runs of the same character, digits are left alone so numbers keep their value
+fn trim_repeats(text: &str) -> String {
+    let mut trimmed = String::with_capacity(text.len());
+    let mut previous = None;
+    let mut run = 0;
+    for c in text.chars() {
+        if Some(c) == previous {
+            run += 1;
+        } else {
+            previous = Some(c);
+            run = 1;
+        }
+        if run <= MAX_REPEAT || c.is_ascii_digit() {
+            trimmed.push(c);
+        }
+    }
+    trimmed
+}
+
+/// Cuts at the last word that fits
+fn cap_length(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let cut: String = text.chars().take(max_chars).collect();
+    match cut.rfind(' ') {
+        Some(end) => cut[..end].to_string(),
+        None => cut,
+    }
+}
+
+const ONES: [&str; 10] = [
+    "ноль",
+    "один",
+    "два",
+    "три",
+    "четыре",
+    "пять",
+    "шесть",
+    "семь",
+    "восемь",
+    "девять",
+];
+const TEENS: [&str; 10] = [
+    "десять",
+    "одиннадцать",
+    "двенадцать",
+    "тринадцать",
+    "четырнадцать",
+    "пятнадцать",
+    "шестнадцать",
+    "семнадцать",
+    "восемнадцать",
+    "девятнадцать",
+];
+const TENS: [&str; 10] = [
+    "",
+    "",
+    "двадцать",
+    "тридцать",
+    "сорок",
+    "пятьдесят",
+    "шестьдесят",
+    "семьдесят",
+    "восемьдесят",
+    "девяносто",
+];
+const HUNDREDS: [&str; 10] = [
+    "",
+    "сто",
+    "двести",
+    "триста",
+    "четыреста",
+    "пятьсот",
+    "шестьсот",
+    "семьсот",
+    "восемьсот",
+    "девятьсот",
+];
+/// Singular, few and many forms, thousands are feminine
+const SCALES: [(&str, &str, &str, bool); 3] = [
+    ("тысяча", "тысячи", "тысяч", true),
+    ("миллион", "миллиона", "миллионов", false),
+    ("миллиард", "миллиарда", "миллиардов", false),
+];
+
+/// Number as Russian words. Leading zeros and numbers above billions are read digit by digit.
+fn verbalize(digits: &str) -> String {
+    if (digits.len() > 1 && digits.starts_with('0')) || digits.len() > 12 {
+        return digits
+            .chars()
+            .filter_map(|c| c.to_digit(10))
+            .map(|d| ONES[d as usize])
+            .collect::<Vec<_>>()
+            .join(" ");
+    }
+    let Ok(number) = digits.parse::<u64>() else {
+        return digits.to_string();
+    };
+    if number == 0 {
+        return ONES[0].to_string();
+    }
+    let mut words = Vec::new();
+    for (index, (one, few, many, feminine)) in SCALES.iter().enumerate().rev() {
+        let group = (number / 1000u64.pow(index as u32 + 1)) % 1000;
+        if group > 0 {
+            words.extend(triple(group, *feminine));
+            words.push(plural(group, one, few, many));
+        }
+    }
+    words.extend(triple(number % 1000, false));
+    words.join(" ")
+}
+
+/// Words for 1..=999, empty for 0
+fn triple(number: u64, feminine: bool) -> Vec<&'static str> {
+    let mut words = Vec::new();
+    let (hundreds, rest) = (number / 100, number % 100);
+    if hundreds > 0 {
+        words.push(HUNDREDS[hundreds as usize]);
+    }
+    if (10..20).contains(&rest) {
+        words.push(TEENS[(rest - 10) as usize]);
+        return words;
+    }
+    let (tens, ones) = (rest / 10, rest % 10);
+    if tens > 0 {
+        words.push(TENS[tens as usize]);
+    }
+    match (ones, feminine) {
+        (0, _) => {}
+        (1, true) => words.push("одна"),
+        (2, true) => words.push("две"),
+        (ones, _) => words.push(ONES[ones as usize]),
+    }
+    words
+}
+
+fn plural(number: u64, one: &'static str, few: &'static str, many: &'static str) -> &'static str {
+    match (number % 10, number % 100) {
+        (_, 11..=14) => many,
+        (1, _) => one,
+        (2..=4, _) => few,
+        _ => many,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verbalizes_units_and_teens() {
+        assert_eq!(verbalize("0"), "ноль");
+        assert_eq!(verbalize("1"), "один");
+        assert_eq!(verbalize("2"), "два");
+        assert_eq!(verbalize("5"), "пять");
+        assert_eq!(verbalize("11"), "одиннадцать");
+        assert_eq!(verbalize("21"), "двадцать один");
+        assert_eq!(verbalize("112"), "сто двенадцать");
+    }
+
+    #[test]
+    fn verbalizes_feminine_thousands() {
+        assert_eq!(verbalize("1001"), "одна тысяча один");
+        assert_eq!(verbalize("2000"), "две тысячи");
+        assert_eq!(verbalize("5000"), "пять тысяч");
+        assert_eq!(verbalize("11000"), "одиннадцать тысяч");
+        assert_eq!(verbalize("21000"), "двадцать одна тысяча");
+    }
+
+    #[test]
+    fn verbalizes_millions_and_billions() {
+        assert_eq!(verbalize("1000000"), "один миллион");
+        assert_eq!(verbalize("3000000"), "три миллиона");
+        assert_eq!(
+            verbalize("1234567"),
+            "один миллион двести тридцать четыре тысячи пятьсот шестьдесят семь"
+        );
+        assert_eq!(verbalize("2000000000"), "два миллиарда");
+    }
+
+    #[test]
+    fn reads_leading_zeros_and_long_numbers_by_digit() {
+        assert_eq!(verbalize("007"), "ноль ноль семь");
+        assert_eq!(
+            verbalize("1234567890123"),
+            "один два три четыре пять шесть семь восемь девять ноль один два три"
+        );
+    }
+
+    #[test]
+    fn verbalizes_decimals() {
+        assert_eq!(normalize("1,5"), "один запятая пять");
+        assert_eq!(normalize("3.25"), "три запятая двадцать пять");
+    }
+
+    #[test]
+    fn separates_numbers_glued_to_words() {
+        assert_eq!(
+            normalize("Gamer1337"),
+            "Gamer одна тысяча триста тридцать семь"
+        );
+    }
+
+    #[test]
+    fn collapses_urls() {
+        assert_eq!(
+            normalize("смотри https://example.com/a?b=1 и www.test.ru"),
+            "смотри ссылка и ссылка"
+        );
+        assert_eq!(normalize("зайди на twitch.tv/bot"), "зайди на ссылка");
+    }
+
+    #[test]
+    fn removes_emotes_and_emoji() {
+        assert_eq!(normalize("Kappa круто 😂😂 PogChamp"), "круто");
+        // emotes only count as whole words
+        assert_eq!(normalize("Kappas"), "Kappas");
+    }
+
+    #[test]
+    fn replaces_profanity() {
+        assert_eq!(normalize("ну ты сука"), "ну ты пип");
+        assert_eq!(normalize("Бляха"), "пип");
+        // stems only match at the start of a word
+        assert_eq!(normalize("страхуем"), "страхуем");
+    }
+
+    #[test]
+    fn replaces_stretched_profanity() {
+        assert_eq!(normalize("сууука"), "пип");
+        assert_eq!(normalize("сууууууука"), "пип");
+    }
+
+    #[test]
+    fn trims_repeated_characters() {
+        assert_eq!(normalize("дааааааа!!!!!!"), "дааа!!!");
+        // digits keep their value
+        assert_eq!(normalize("1111"), "одна тысяча сто одиннадцать");
+    }
+
+    #[test]
+    fn caps_length_at_a_word() {
+        assert_eq!(cap_length("один два три четыре", 12), "один два");
+        assert_eq!(cap_length("один", 12), "один");
+        assert_eq!(cap_length("оченьдлинноеслово", 5), "очень");
+        let long = "слово ".repeat(100);
+        assert!(normalize(&long).chars().count() <= 300);
+    }
+}
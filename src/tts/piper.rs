@@ -0,0 +1,70 @@
+use crate::tts::{TtsEngine, VoiceSettings, engine_timeout, kill_process, play_rendered, player};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use std::env;
+use std::path::Path;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// Neural `piper` voices, the voice is the path of an `.onnx` model, default `PIPER_MODEL`
+pub struct PiperEngine {
+    default_model: Option<String>,
+}
+
+impl PiperEngine {
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(Self {
+            default_model: env::var("PIPER_MODEL").ok(),
+        })
+    }
+}
+
+#[async_trait]
+impl TtsEngine for PiperEngine {
+    fn name(&self) -> &'static str {
+        "piper"
+    }
+
+    async fn speak(&self, text: &str, voice: &VoiceSettings) -> anyhow::Result<()> {
+        play_rendered(self, text, voice).await
+    }
+
+    async fn render(&self, text: &str, voice: &VoiceSettings, path: &Path) -> anyhow::Result<()> {
+        let model = voice
+            .voice
+            .clone()
+            .or(self.default_model.clone())
+            .ok_or(anyhow!("No piper model, set PIPER_MODEL or TTS_VOICE"))?;
+        // piper speaks at roughly 175 words per minute with the default length scale
+        let length_scale = 175.0 / voice.rate.max(1) as f32;
+        let mut child = Command::new("piper")
+            .arg("--model")
+            .arg(&model)
+            .arg("--length_scale")
+            .arg(length_scale.to_string())
+            .arg("--output_file")
+            .arg(path)
+            .stdin(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        let run = async {
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(text.as_bytes()).await?;
+            }
+            child.wait().await
+        };
+        // piper is killed on drop when it hangs
+        let status = tokio::time::timeout(engine_timeout(), run)
+            .await
+            .map_err(|_| anyhow!("piper timed out after {:?}", engine_timeout()))??;
+        if !status.success() {
+            return Err(anyhow!("piper exited with {status}"));
+        }
+        Ok(())
+    }
+
+    async fn stop(&self) -> anyhow::Result<()> {
+        kill_process(&player()).await
+    }
+}
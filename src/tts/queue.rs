@@ -0,0 +1,297 @@
+use crate::jobs::worker_id;
+use crate::pg::pg::PgConnect;
+use crate::redact::redact;
+use crate::tts::normalize::normalize;
+use crate::tts::{self, VoiceSettings};
+use crate::twitch::chat_message::{ChatMessage, MessageCommands};
+use serde::Serialize;
+use std::env;
+use std::str::FromStr;
+use tokio::time::{Duration, interval};
+use tokio_postgres::{Client, Row};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+/// Announcement waiting to be spoken
+#[derive(Debug, Serialize)]
+pub struct TtsItem {
+    pub id: i64,
+    pub message_id: Uuid,
+    pub command: MessageCommands,
+    pub sender: String,
+    pub text: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TtsQueueState {
+    pub volume: i32,
+    pub skips: i32,
+    pub speaking: Option<i64>,
+    pub pending: i64,
+}
+
+/// What the overlay needs to play clips, see `TTS_OUTPUT=files`
+#[derive(Debug, Serialize)]
+pub struct TtsControls {
+    pub volume: i32,
+    /// Counter bumped on every skip, a change means the playing clip should stop
+    pub skips: i32,
+}
+
+/// Announcements are spoken one after another by the worker, separately from the agent jobs,
+/// so a long utterance never holds up the queue and can be skipped on its own
+pub struct TtsQueue {}
+
+impl TtsQueue {
+    /// Queues the message for reading out, commands missing from `TTS_COMMANDS` are left silent
+    pub async fn enqueue(msg: &ChatMessage) -> anyhow::Result<()> {
+        if !tts::is_announced(&msg.command) {
+            return Ok(());
+        }
+        let message_id = Uuid::from_str(msg.id.as_deref().unwrap_or_default())?;
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query =
+            "INSERT INTO tts_queue (message_id, command, sender, text) VALUES ($1, $2, $3, $4)";
+        client
+            .execute(
+                query,
+                &[
+                    &message_id,
+                    &msg.command.to_string(),
+                    &msg.username,
+                    &msg.text,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Speaks pending announcements until shutdown
+    pub async fn run(shutdown: CancellationToken) {
+        let worker = worker_id();
+        match Self::release_orphans(&worker).await {
+            Ok(0) => {}
+            Ok(released) => info!("Failed {released} announcements left speaking by {worker}"),
+            Err(err) => error!("Error releasing orphaned announcements {:?}", err),
+        }
+        let mut ticker = interval(Duration::from_secs(1));
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown.cancelled() => {
+                    info!("TTS queue stopped");
+                    return;
+                }
+            }
+            loop {
+                match Self::claim(&worker).await {
+                    Ok(Some(item)) => Self::speak_watched(&item).await,
+                    Ok(None) => break,
+                    Err(err) => {
+                        error!("Error polling TTS queue {:?}", err);
+                        break;
+                    }
+                }
+                if shutdown.is_cancelled() {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Announcements this worker was speaking when it died, they are not repeated
+    async fn release_orphans(worker: &str) -> anyhow::Result<u64> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "UPDATE tts_queue SET status = 'FAILED', finished_at = now() \
+            WHERE status = 'SPEAKING' AND worker_id = $1";
+        Ok(client.execute(query, &[&worker]).await?)
+    }
+
+    /// Longest an announcement may stay `SPEAKING`, `TTS_LEASE_SECS` (default `120`)
+    fn lease_secs() -> f64 {
+        env::var("TTS_LEASE_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(120.0)
+    }
+
+    /// Oldest pending announcement, nothing while another one is being spoken.
+    /// Announcements speaking past their lease belong to a dead worker and are failed first.
+    async fn claim(worker: &str) -> anyhow::Result<Option<TtsItem>> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "UPDATE tts_queue SET status = 'FAILED', finished_at = now() WHERE status = 'SPEAKING' \
+            AND (started_at IS NULL OR started_at < now() - make_interval(secs => $1))";
+        let expired = client.execute(query, &[&Self::lease_secs()]).await?;
+        if expired > 0 {
+            warn!("Failed {expired} announcements speaking past their lease");
+        }
+        let query = "UPDATE tts_queue SET status = 'SPEAKING', worker_id = $1, started_at = now() WHERE id = \
+            (SELECT id FROM tts_queue WHERE status = 'PENDING' \
+            AND NOT EXISTS (SELECT 1 FROM tts_queue WHERE status = 'SPEAKING') \
+            ORDER BY id LIMIT 1 FOR UPDATE SKIP LOCKED) RETURNING *";
+        let row = client.query_opt(query, &[&worker]).await?;
+        row.as_ref().map(Self::from_row).transpose()
+    }
+
+    /// Speaks an item and stops the engine as soon as it is skipped
+    async fn speak_watched(item: &TtsItem) {
+        let mut watcher = interval(Duration::from_millis(500));
+        let speech = Self::speak(item);
+        tokio::pin!(speech);
+        let status = loop {
+            tokio::select! {
+                result = &mut speech => match result {
+                    Ok(()) => break "DONE",
+                    Err(err) => {
+                        error!("Error speaking announcement {} {:?}", item.id, err);
+                        break "FAILED";
+                    }
+                },
+                _ = watcher.tick() => {
+                    match Self::status(item.id).await {
+                        Ok(status) if status == "SPEAKING" => {}
+                        Ok(status) => {
+                            info!("Announcement {} is {status}, stopping", item.id);
+                            if let Err(err) = tts::stop_speaker().await {
+                                error!("Error stopping TTS {:?}", err);
+                            }
+                            return;
+                        }
+                        Err(err) => error!("Error watching announcement {:?}", err),
+                    }
+                }
+            }
+        };
+        if let Err(err) = Self::finish(item.id, status).await {
+            error!("Error finishing announcement {} {:?}", item.id, err);
+        }
+    }
+
+    async fn speak(item: &TtsItem) -> anyhow::Result<()> {
+        let mut voice = VoiceSettings::for_command(&item.command);
+        voice.volume = Self::volume().await?;
+        let utterance = normalize(&redact(&voice.utterance(&item.sender, &item.text), "tts"));
+        tts::announce(
+            &item.message_id.to_string(),
+            &item.command,
+            &utterance,
+            &voice,
+        )
+        .await
+    }
+
+    async fn status(id: i64) -> anyhow::Result<String> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "SELECT status FROM tts_queue WHERE id = $1";
+        Ok(client.query_one(query, &[&id]).await?.try_get("status")?)
+    }
+
+    async fn finish(id: i64, status: &str) -> anyhow::Result<()> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "UPDATE tts_queue SET status = $1, finished_at = now() WHERE id = $2 AND status = 'SPEAKING'";
+        client.execute(query, &[&status, &id]).await?;
+        Ok(())
+    }
+
+    /// Cuts off the announcement being spoken, the next one starts right after.
+    /// The overlay stops the clip it is playing as well.
+    pub async fn skip_current() -> anyhow::Result<u64> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "UPDATE tts_queue SET status = 'SKIPPED', finished_at = now() WHERE status = 'SPEAKING'";
+        let skipped = client.execute(query, &[]).await?;
+        Self::bump_skips(&client).await?;
+        Ok(skipped)
+    }
+
+    /// Drops the current and every pending announcement
+    pub async fn skip_all() -> anyhow::Result<u64> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "UPDATE tts_queue SET status = 'SKIPPED', finished_at = now() \
+            WHERE status IN ('PENDING', 'SPEAKING')";
+        let skipped = client.execute(query, &[]).await?;
+        Self::bump_skips(&client).await?;
+        Ok(skipped)
+    }
+
+    async fn bump_skips(client: &Client) -> anyhow::Result<()> {
+        let query = "UPDATE tts_state SET skips = skips + 1, updated_at = now() WHERE id = 1";
+        client.execute(query, &[]).await?;
+        Ok(())
+    }
+
+    /// Volume in percent, applied from the next announcement on
+    pub async fn set_volume(volume: i32) -> anyhow::Result<()> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "UPDATE tts_state SET volume = $1, updated_at = now() WHERE id = 1";
+        client.execute(query, &[&volume.clamp(0, 100)]).await?;
+        Ok(())
+    }
+
+    pub async fn volume() -> anyhow::Result<i32> {
+        Ok(Self::controls().await?.volume)
+    }
+
+    pub async fn controls() -> anyhow::Result<TtsControls> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "SELECT volume, skips FROM tts_state WHERE id = 1";
+        let row = client.query_opt(query, &[]).await?;
+        Ok(match row {
+            Some(row) => TtsControls {
+                volume: row.try_get("volume")?,
+                skips: row.try_get("skips")?,
+            },
+            None => TtsControls {
+                volume: 100,
+                skips: 0,
+            },
+        })
+    }
+
+    pub async fn state() -> anyhow::Result<TtsQueueState> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let speaking = client
+            .query_opt(
+                "SELECT id FROM tts_queue WHERE status = 'SPEAKING' LIMIT 1",
+                &[],
+            )
+            .await?
+            .map(|row| row.try_get::<_, i64>(0))
+            .transpose()?;
+        let pending: i64 = client
+            .query_one(
+                "SELECT count(*) FROM tts_queue WHERE status = 'PENDING'",
+                &[],
+            )
+            .await?
+            .try_get(0)?;
+        let controls = Self::controls().await?;
+        Ok(TtsQueueState {
+            volume: controls.volume,
+            skips: controls.skips,
+            speaking,
+            pending,
+        })
+    }
+
+    fn from_row(row: &Row) -> anyhow::Result<TtsItem> {
+        let command: String = row.try_get("command")?;
+        Ok(TtsItem {
+            id: row.try_get("id")?,
+            message_id: row.try_get("message_id")?,
+            command: MessageCommands::from_str(&command)?,
+            sender: row.try_get("sender")?,
+            text: row.try_get("text")?,
+        })
+    }
+}
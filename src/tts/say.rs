@@ -0,0 +1,61 @@
+use crate::tts::{TtsEngine, VoiceSettings, kill_process};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use std::path::Path;
+use tokio::process::Command;
+
+/// `say` has no volume flag, the embedded `volm` command sets it for the utterance
+fn with_volume(text: &str, voice: &VoiceSettings) -> String {
+    format!(
+        "[[volm {:.2}]] {text}",
+        voice.volume.clamp(0, 100) as f32 / 100.0
+    )
+}
+
+/// macOS `say`, voice defaults to `Milena`
+pub struct SayEngine {}
+
+#[async_trait]
+impl TtsEngine for SayEngine {
+    fn name(&self) -> &'static str {
+        "say"
+    }
+
+    async fn speak(&self, text: &str, voice: &VoiceSettings) -> anyhow::Result<()> {
+        let status = Command::new("say")
+            .arg("-v")
+            .arg(voice.voice.as_deref().unwrap_or("Milena"))
+            .arg("-r")
+            .arg(voice.rate.to_string())
+            .arg(with_volume(text, voice))
+            .kill_on_drop(true)
+            .status()
+            .await?;
+        if !status.success() {
+            return Err(anyhow!("say exited with {status}"));
+        }
+        Ok(())
+    }
+
+    async fn render(&self, text: &str, voice: &VoiceSettings, path: &Path) -> anyhow::Result<()> {
+        let status = Command::new("say")
+            .arg("-v")
+            .arg(voice.voice.as_deref().unwrap_or("Milena"))
+            .arg("-r")
+            .arg(voice.rate.to_string())
+            .arg("-o")
+            .arg(path)
+            .args(["--file-format=WAVE", "--data-format=LEI16@22050"])
+            .arg(with_volume(text, voice))
+            .status()
+            .await?;
+        if !status.success() {
+            return Err(anyhow!("say exited with {status}"));
+        }
+        Ok(())
+    }
+
+    async fn stop(&self) -> anyhow::Result<()> {
+        kill_process("say").await
+    }
+}
@@ -1,5 +1,8 @@
+use crate::jobs::history::{Actor, execute_as};
 use crate::pg::pg::PgConnect;
+use crate::twitch::dedup;
 use anyhow::anyhow;
+use chrono::{DateTime, Utc};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::error::Error;
@@ -13,11 +16,14 @@ use uuid::Uuid;
 type GlobalError = Box<dyn Error>;
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ChatMessage {
-    id: Option<String>,
+    pub(crate) id: Option<String>,
     pub command: MessageCommands,
     pub(crate) text: String,
     pub(crate) username: String,
     status: MessageStatus,
+    /// Emote codes used in the message, ignored by duplicate detection
+    #[serde(skip)]
+    emotes: Vec<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -26,6 +32,12 @@ pub enum MessageStatus {
     Awaiting,
     InProcess,
     Completed,
+    Failed,
+    Skipped,
+    Rejected,
+    Expired,
+    /// Stopped by the kill switch
+    Aborted,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -34,6 +46,12 @@ pub enum MessageCommands {
     SetTheme,
     SetSong,
     AIReply,
+    AdminMessage,
+    CompanionMessage,
+    /// Reverts the workspace changes of the job whose id is the text
+    Undo,
+    /// Applies the sandbox changes of the job whose id is the text to the real project
+    Promote,
     Unknown,
 }
 
@@ -44,6 +62,10 @@ impl Display for MessageCommands {
             MessageCommands::SetTheme => "!SET".to_string(),
             MessageCommands::SetSong => "!PLAY".to_string(),
             MessageCommands::AIReply => "!REPLY".to_string(),
+            MessageCommands::AdminMessage => "ADMIN".to_string(),
+            MessageCommands::CompanionMessage => "COMPANION".to_string(),
+            MessageCommands::Undo => "!UNDO".to_string(),
+            MessageCommands::Promote => "!PROMOTE".to_string(),
             MessageCommands::Unknown => "UNKNOWN".to_string(),
         };
         write!(f, "{}", str)
@@ -57,6 +79,11 @@ impl FromStr for MessageCommands {
             "!STORE" => MessageCommands::StoreChatMessage,
             "!SET" => MessageCommands::SetTheme,
             "!PLAY" => MessageCommands::SetSong,
+            "!REPLY" => MessageCommands::AIReply,
+            "ADMIN" => MessageCommands::AdminMessage,
+            "COMPANION" => MessageCommands::CompanionMessage,
+            "!UNDO" => MessageCommands::Undo,
+            "!PROMOTE" => MessageCommands::Promote,
             _ => MessageCommands::Unknown,
         };
         Ok(command)
@@ -71,6 +98,11 @@ impl FromStr for MessageStatus {
             "AWAITING" => MessageStatus::Awaiting,
             "COMPLETED" => MessageStatus::Completed,
             "IN_PROCESS" => MessageStatus::InProcess,
+            "FAILED" => MessageStatus::Failed,
+            "SKIPPED" => MessageStatus::Skipped,
+            "REJECTED" => MessageStatus::Rejected,
+            "EXPIRED" => MessageStatus::Expired,
+            "ABORTED" => MessageStatus::Aborted,
             _ => {
                 return Err(anyhow!("Error from str for MessageStatus").into_boxed_dyn_error());
             }
@@ -86,6 +118,11 @@ impl Display for MessageStatus {
             MessageStatus::Awaiting => "AWAITING".to_string(),
             MessageStatus::Completed => "COMPLETED".to_string(),
             MessageStatus::InProcess => "IN_PROCESS".to_string(),
+            MessageStatus::Failed => "FAILED".to_string(),
+            MessageStatus::Skipped => "SKIPPED".to_string(),
+            MessageStatus::Rejected => "REJECTED".to_string(),
+            MessageStatus::Expired => "EXPIRED".to_string(),
+            MessageStatus::Aborted => "ABORTED".to_string(),
         };
         write!(f, "{}", str)
     }
@@ -104,8 +141,14 @@ impl ChatMessage {
             text,
             username,
             status,
+            emotes: Vec::new(),
         }
     }
+
+    pub fn with_emotes(mut self, emotes: Vec<String>) -> Self {
+        self.emotes = emotes;
+        self
+    }
     pub fn from_raw_message(full_message: String, username: String) -> anyhow::Result<Self> {
         info!("from_raw {}", full_message);
         match Self::parse(&full_message) {
@@ -137,14 +180,6 @@ impl ChatMessage {
         }
     }
 
-    async fn if_exists(&self) -> anyhow::Result<bool> {
-        let query = "SELECT * FROM chat_messages WHERE text = $1";
-        let pool = PgConnect::create_pool_from_env()?;
-        let client = pool.get().await?;
-        let rows = client.query(query, &[&self.text]).await?;
-        Ok(rows.len() > 0)
-    }
-
     async fn is_duplicate_theme(&self) -> anyhow::Result<bool> {
         let query = "SELECT * FROM chat_messages WHERE username = $1 and status = $2";
         let pool = PgConnect::create_pool_from_env()?;
@@ -161,8 +196,13 @@ impl ChatMessage {
         info!("Got message: {:?}", self);
         match &self.command {
             MessageCommands::StoreChatMessage => {
-                if !self.if_exists().await? {
-                    let query = "INSERT INTO chat_messages ( username, text, command, status ) VALUES ($1, $2, $3, $4)";
+                let normalized = dedup::normalize(&self.text, &self.emotes);
+                if normalized.is_empty() || dedup::is_duplicate(&client, &normalized).await? {
+                    info!("Skipping duplicate prompt {:?}", self.text);
+                } else {
+                    // Conflicts with the partial unique index mean the same prompt is already queued
+                    let query = "INSERT INTO chat_messages ( username, text, command, status, normalized_text, normalized_hash ) \
+                        VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT DO NOTHING";
                     client
                         .query(
                             query,
@@ -171,6 +211,8 @@ impl ChatMessage {
                                 &self.text.trim(),
                                 &self.command.to_string().as_str().trim(),
                                 &MessageStatus::Unverified.to_string(),
+                                &normalized,
+                                &dedup::hash(&normalized),
                             ],
                         )
                         .await?;
@@ -220,22 +262,83 @@ impl ChatMessage {
                     )
                     .await?;
             }
-            MessageCommands::Unknown => {
+            MessageCommands::AdminMessage
+            | MessageCommands::CompanionMessage
+            | MessageCommands::Undo
+            | MessageCommands::Promote
+            | MessageCommands::Unknown => {
                 info!("Skipping message {:?}", self);
             }
         };
         Ok(())
     }
 
-    pub async fn update_status(&self, status: MessageStatus) -> anyhow::Result<()> {
+    /// Inserts straight into the queue, skipping the verification and duplicate checks of `insert`.
+    /// Jobs with `run_at` in the future are not picked up before that time.
+    pub async fn enqueue(&self, run_at: Option<DateTime<Utc>>) -> anyhow::Result<()> {
         let pool = PgConnect::create_pool_from_env()?;
         let client = pool.get().await?;
+        let query = "INSERT INTO chat_messages ( username, text, command, status, run_at ) VALUES ($1, $2, $3, $4, COALESCE($5, now()))";
+        client
+            .execute(
+                query,
+                &[
+                    &self.username,
+                    &self.text.trim(),
+                    &self.command.to_string(),
+                    &MessageStatus::Awaiting.to_string(),
+                    &run_at,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn update_status(
+        &self,
+        status: MessageStatus,
+        actor: &Actor,
+        reason: Option<&str>,
+    ) -> anyhow::Result<()> {
         let query = "UPDATE chat_messages SET status = $1 WHERE id = $2";
         let id = Uuid::from_str(self.id.as_ref().unwrap())?;
-        client.query(query, &[&status.to_string(), &id]).await?;
+        execute_as(actor, reason, query, &[&status.to_string(), &id]).await?;
         Ok(())
     }
 
+    pub async fn current_status(&self) -> anyhow::Result<MessageStatus> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "SELECT status FROM chat_messages WHERE id = $1";
+        let id = Uuid::from_str(self.id.as_ref().unwrap())?;
+        let status: String = client.query_one(query, &[&id]).await?.try_get("status")?;
+        MessageStatus::from_str(&status).map_err(|e| anyhow!("{e}"))
+    }
+
+    /// Resolves a full id or a unique id prefix, so mods don't have to type whole uuids in chat
+    pub async fn resolve_id(id_or_prefix: &str) -> anyhow::Result<Uuid> {
+        if let Ok(id) = Uuid::from_str(id_or_prefix) {
+            return Ok(id);
+        }
+        if id_or_prefix.is_empty()
+            || !id_or_prefix
+                .chars()
+                .all(|c| c.is_ascii_hexdigit() || c == '-')
+        {
+            return Err(anyhow!("Invalid message id {id_or_prefix}"));
+        }
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "SELECT id FROM chat_messages WHERE id::text LIKE $1 LIMIT 2";
+        let pattern = format!("{}%", id_or_prefix.to_lowercase());
+        let rows = client.query(query, &[&pattern]).await?;
+        match rows.as_slice() {
+            [row] => Ok(row.try_get("id")?),
+            [] => Err(anyhow!("No message with id {id_or_prefix}")),
+            _ => Err(anyhow!("Id prefix {id_or_prefix} is ambiguous")),
+        }
+    }
+
     fn row_to_chat_message(row: &Row) -> anyhow::Result<ChatMessage> {
         let id: Uuid = row.try_get("id")?;
         let command: String = row.try_get("command")?;
@@ -257,39 +360,44 @@ impl ChatMessage {
         let pool = PgConnect::create_pool_from_env()?;
         let client = pool.get().await?;
         let rows = client.query(query, &[]).await?;
-        let chat_messages: Vec<ChatMessage> = rows
-            .into_iter()
-            .map(|row| Self::row_to_chat_message(&row).unwrap())
-            .collect();
-        Ok(chat_messages)
+        rows.iter().map(Self::row_to_chat_message).collect()
     }
 
-    pub async fn bulk_update(ids: &Vec<String>) -> anyhow::Result<()> {
-        for id in ids {
-            let query = "UPDATE chat_messages SET status = $1 WHERE status = $2 and id = $3";
-            let pool = PgConnect::create_pool_from_env()?;
-            let client = pool.get().await?;
-            let id = Uuid::from_str(id)?;
-            client
-                .query(
-                    query,
-                    &[
-                        &MessageStatus::Awaiting.to_string(),
-                        &MessageStatus::Unverified.to_string(),
-                        &id,
-                    ],
-                )
-                .await?;
-        }
-        Ok(())
+    /// Approves unverified messages in one statement, returns how many were moved to the queue
+    pub async fn bulk_update(ids: &[String], actor: &Actor) -> anyhow::Result<u64> {
+        let ids = ids
+            .iter()
+            .map(|id| Uuid::from_str(id))
+            .collect::<Result<Vec<_>, _>>()?;
+        // approving a prompt held by screening overrides the screening
+        let query = "UPDATE chat_messages SET status = $1, screening_override = screening IS NOT NULL \
+            WHERE status = $2 and id = ANY($3)";
+        execute_as(
+            actor,
+            Some("approved"),
+            query,
+            &[
+                &MessageStatus::Awaiting.to_string(),
+                &MessageStatus::Unverified.to_string(),
+                &ids,
+            ],
+        )
+        .await
     }
 
     pub async fn get_admin_message() -> anyhow::Result<Self> {
         let pool = PgConnect::create_pool_from_env()?;
         let client = pool.get().await?;
-        let query = "SELECT * FROM chat_messages WHERE username=$1 and status = $2 ORDER BY created_at asc LIMIT 1";
+        let query = "SELECT * FROM chat_messages WHERE username=$1 and command = $2 and status = $3 and run_at <= now() ORDER BY created_at asc LIMIT 1";
         let message = client
-            .query_one(query, &[&"admin", &MessageStatus::Awaiting.to_string()])
+            .query_one(
+                query,
+                &[
+                    &"admin",
+                    &MessageCommands::AdminMessage.to_string(),
+                    &MessageStatus::Awaiting.to_string(),
+                ],
+            )
             .await?;
         let id: Uuid = message.try_get("id")?;
         let command: String = message.try_get("command")?;
@@ -307,9 +415,16 @@ impl ChatMessage {
     pub async fn get_companion_message() -> anyhow::Result<Self> {
         let pool = PgConnect::create_pool_from_env()?;
         let client = pool.get().await?;
-        let query = "SELECT * FROM chat_messages WHERE username = $1 and status = $2 ORDER BY created_at asc LIMIT 1";
+        let query = "SELECT * FROM chat_messages WHERE username = $1 and command = $2 and status = $3 and run_at <= now() ORDER BY created_at asc LIMIT 1";
         let message = client
-            .query_one(query, &[&"companion", &MessageStatus::Awaiting.to_string()])
+            .query_one(
+                query,
+                &[
+                    &"companion",
+                    &MessageCommands::CompanionMessage.to_string(),
+                    &MessageStatus::Awaiting.to_string(),
+                ],
+            )
             .await?;
         let id: Uuid = message.try_get("id")?;
         let command: String = message.try_get("command")?;
@@ -327,7 +442,7 @@ impl ChatMessage {
     pub async fn get_user_chat_message() -> anyhow::Result<Self> {
         let pool = PgConnect::create_pool_from_env()?;
         let client = pool.get().await?;
-        let query = "SELECT * FROM chat_messages WHERE status='AWAITING' AND command = '!REPLY' ORDER BY created_at asc LIMIT 1";
+        let query = "SELECT * FROM chat_messages WHERE status='AWAITING' AND command = '!REPLY' AND run_at <= now() ORDER BY created_at asc LIMIT 1";
         let message = client.query_one(query, &[]).await?;
         // todo move to impl
         let id: Uuid = message.try_get("id")?;
@@ -0,0 +1,181 @@
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::env;
+use tokio_postgres::Client;
+
+/// Cyrillic letters that look like latin ones, folded so `сat` and `cat` hash the same.
+/// Russian prompts are folded too, `кот` becomes `kot`, so the key is only for comparing.
+const HOMOGLYPHS: [(char, char); 16] = [
+    ('а', 'a'),
+    ('в', 'b'),
+    ('е', 'e'),
+    ('ё', 'e'),
+    ('і', 'i'),
+    ('ј', 'j'),
+    ('к', 'k'),
+    ('м', 'm'),
+    ('н', 'h'),
+    ('о', 'o'),
+    ('р', 'p'),
+    ('с', 'c'),
+    ('т', 't'),
+    ('у', 'y'),
+    ('х', 'x'),
+    ('ѕ', 's'),
+];
+
+/// Lowercases, drops emotes and punctuation, folds homoglyphs and collapses whitespace
+pub fn normalize(text: &str, emotes: &[String]) -> String {
+    let words = text
+        .split_whitespace()
+        .filter(|word| !emotes.iter().any(|emote| emote == word))
+        .map(|word| {
+            word.to_lowercase()
+                .chars()
+                .filter(|c| c.is_alphanumeric())
+                .map(|c| {
+                    HOMOGLYPHS
+                        .iter()
+                        .find(|(from, _)| *from == c)
+                        .map_or(c, |(_, to)| *to)
+                })
+                .collect::<String>()
+        })
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>();
+    words.join(" ")
+}
+
+pub fn hash(normalized: &str) -> String {
+    format!("{:x}", Sha256::digest(normalized.as_bytes()))
+}
+
+fn shingles(normalized: &str) -> HashSet<String> {
+    let chars: Vec<char> = normalized.chars().collect();
+    if chars.len() < 3 {
+        return HashSet::from([normalized.to_string()]);
+    }
+    chars.windows(3).map(|w| w.iter().collect()).collect()
+}
+
+/// Jaccard similarity of character trigrams, 1.0 means identical
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (shingles(a), shingles(b));
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 1.0;
+    }
+    a.intersection(&b).count() as f64 / union as f64
+}
+
+fn window_minutes() -> i32 {
+    env::var("DUPLICATE_WINDOW_MINUTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(24 * 60)
+}
+
+fn near_duplicate_threshold() -> f64 {
+    env::var("NEAR_DUPLICATE_THRESHOLD")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0.8)
+}
+
+/// Checks the normalized prompt against prompts of the last `DUPLICATE_WINDOW_MINUTES`,
+/// exact matches go through the hash index, near duplicates of the window are compared by trigram similarity
+pub async fn is_duplicate(client: &Client, normalized: &str) -> anyhow::Result<bool> {
+    let window = window_minutes();
+    let query = "SELECT 1 FROM chat_messages WHERE normalized_hash = $1 \
+        AND created_at > now() - make_interval(mins => $2) LIMIT 1";
+    if client
+        .query_opt(query, &[&hash(normalized), &window])
+        .await?
+        .is_some()
+    {
+        return Ok(true);
+    }
+    // the window is the only bound, a row cap would let older prompts of the window through
+    let query = "SELECT DISTINCT normalized_text FROM chat_messages WHERE normalized_text IS NOT NULL \
+        AND created_at > now() - make_interval(mins => $1)";
+    let threshold = near_duplicate_threshold();
+    let recent = client.query(query, &[&window]).await?;
+    Ok(recent.iter().any(|row| {
+        row.try_get::<_, String>("normalized_text")
+            .is_ok_and(|text| similarity(&text, normalized) >= threshold)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_case_punctuation_and_whitespace() {
+        assert_eq!(
+            normalize("  Make   it PINK!!! ", &[]),
+            normalize("make it pink", &[])
+        );
+        assert_eq!(normalize("Hello, world?", &[]), "hello world");
+    }
+
+    #[test]
+    fn drops_emotes() {
+        let emotes = vec!["Kappa".to_string()];
+        assert_eq!(
+            normalize("Kappa make it pink Kappa", &emotes),
+            "make it pink"
+        );
+        // only exact emote words are dropped
+        assert_eq!(normalize("Kappas", &emotes), "kappas");
+    }
+
+    #[test]
+    fn folds_cyrillic_homoglyphs() {
+        // "сat" starts with a cyrillic с
+        assert_eq!(normalize("сat", &[]), normalize("cat", &[]));
+        assert_eq!(hash(&normalize("сat", &[])), hash(&normalize("cat", &[])));
+        // russian text is folded as well
+        assert_eq!(normalize("Кот", &[]), "kot");
+        assert_eq!(normalize("привет", &[]), "пpиbet");
+    }
+
+    #[test]
+    fn punctuation_only_is_empty() {
+        assert_eq!(normalize("!!! ... ???", &[]), "");
+    }
+
+    #[test]
+    fn hash_is_stable_sha256() {
+        assert_eq!(hash("make it pink"), hash("make it pink"));
+        assert_ne!(hash("make it pink"), hash("make it blue"));
+        assert_eq!(hash("").len(), 64);
+    }
+
+    #[test]
+    fn identical_texts_are_fully_similar() {
+        assert_eq!(similarity("make it pink", "make it pink"), 1.0);
+        assert_eq!(similarity("ab", "ab"), 1.0);
+    }
+
+    #[test]
+    fn near_duplicates_pass_the_default_threshold() {
+        let a = normalize("please make the background pink", &[]);
+        let b = normalize("please make the background pinkk", &[]);
+        assert!(similarity(&a, &b) >= 0.8, "{}", similarity(&a, &b));
+    }
+
+    #[test]
+    fn different_prompts_stay_below_the_threshold() {
+        let a = normalize("please make the background pink", &[]);
+        let b = normalize("add a snake game to the page", &[]);
+        assert!(similarity(&a, &b) < 0.8, "{}", similarity(&a, &b));
+        assert_eq!(similarity("abc", "xyz"), 0.0);
+    }
+
+    #[test]
+    fn similarity_is_symmetric() {
+        let (a, b) = ("make it pink", "make it pink now");
+        assert_eq!(similarity(a, b), similarity(b, a));
+    }
+}
@@ -1,11 +1,19 @@
 pub mod chat_message;
+pub mod dedup;
+pub mod mod_command;
+pub mod moderation;
+pub mod notifications;
 use crate::twitch::chat_message::ChatMessage;
+use crate::twitch::mod_command::ModCommand;
+use crate::twitch::notifications::ChatNotification;
 use serde::Deserialize;
 use std::env;
 use std::error::Error;
 use std::fmt::Debug;
 use tokio::task::JoinHandle;
-use tracing::info;
+use tokio::time::{Duration, interval};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info};
 use twitch_irc::login::StaticLoginCredentials;
 use twitch_irc::message::ServerMessage::Privmsg;
 use twitch_irc::{ClientConfig, SecureTCPTransport, TwitchIRCClient};
@@ -17,28 +25,113 @@ pub struct TwitchApi {
 }
 
 impl TwitchApi {
-    pub async fn listen_to_chat() -> Result<(), Box<dyn Error>> {
-        // default configuration is to join chat as anonymous.
-        let config = ClientConfig::default();
+    /// Drains the `chat_notifications` outbox into chat until shutdown
+    async fn send_notifications(
+        client: TwitchIRCClient<SecureTCPTransport, StaticLoginCredentials>,
+        channel: String,
+        shutdown: CancellationToken,
+    ) {
+        let mut ticker = interval(Duration::from_secs(10));
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown.cancelled() => return,
+            }
+            let notifications = match ChatNotification::pending(5).await {
+                Ok(notifications) => notifications,
+                Err(e) => {
+                    error!("Error fetching chat notifications {:?}", e);
+                    continue;
+                }
+            };
+            for notification in notifications {
+                if let Err(e) = client.say(channel.clone(), notification.text.clone()).await {
+                    error!("Error sending chat notification {:?}", e);
+                    break;
+                }
+                if let Err(e) = notification.mark_sent().await {
+                    error!("Error marking chat notification {:?}", e);
+                }
+            }
+        }
+    }
+
+    /// Feeds a chat log through the same ingestion path as live chat, one `username: message` per line
+    pub async fn replay_log(path: &str) -> anyhow::Result<usize> {
+        let log = tokio::fs::read_to_string(path).await?;
+        let mut replayed = 0;
+        for line in log.lines() {
+            let Some((username, text)) = line.split_once(':') else {
+                continue;
+            };
+            if let Ok(chat_message) =
+                ChatMessage::from_raw_message(text.trim().to_string(), username.trim().to_string())
+            {
+                chat_message
+                    .insert()
+                    .await
+                    .map_err(|e| anyhow::anyhow!("{e}"))?;
+                replayed += 1;
+            }
+        }
+        Ok(replayed)
+    }
+
+    pub async fn listen_to_chat(shutdown: CancellationToken) -> Result<(), Box<dyn Error>> {
+        // default configuration is to join chat as anonymous,
+        // with bot credentials the client can also answer in chat.
+        let credentials = match (env::var("TWITCH_BOT_LOGIN"), env::var("TWITCH_BOT_OAUTH")) {
+            (Ok(login), Ok(token)) => Some(StaticLoginCredentials::new(
+                login,
+                Some(token.trim_start_matches("oauth:").to_string()),
+            )),
+            _ => None,
+        };
+        let logged_in = credentials.is_some();
+        let config = match credentials {
+            Some(credentials) => ClientConfig::new_simple(credentials),
+            None => ClientConfig::default(),
+        };
         let (mut incoming_messages, client) =
             TwitchIRCClient::<SecureTCPTransport, StaticLoginCredentials>::new(config);
+        let notifier_shutdown = shutdown.clone();
 
         // first thing you should do: start consuming incoming messages,
         // otherwise they will back up.
         let join_handle: JoinHandle<Result<(), Box<dyn Error + Send + Sync>>> =
             tokio::spawn(async move {
-                while let Some(message) = incoming_messages.recv().await {
+                loop {
+                    let message = tokio::select! {
+                        message = incoming_messages.recv() => match message {
+                            Some(message) => message,
+                            None => break,
+                        },
+                        _ = shutdown.cancelled() => {
+                            info!("Chat listener stopped ingesting");
+                            break;
+                        }
+                    };
                     if let Privmsg(priv_msg) = message {
+                        if ModCommand::is_moderator(&priv_msg)
+                            && let Some(command) = ModCommand::parse(&priv_msg.message_text)
+                        {
+                            if let Err(e) = command.execute(&priv_msg.sender.login).await {
+                                error!("Error executing mod command {:?}", e);
+                            }
+                            continue;
+                        }
                         let msg_id_tag = priv_msg.source.tags.0.get("msg-id");
                         match msg_id_tag {
                             Some(tag) => {
                                 let tag = tag.as_ref().unwrap();
                                 if tag == "highlighted-message" {
+                                    let emotes =
+                                        priv_msg.emotes.iter().map(|e| e.code.clone()).collect();
                                     if let Ok(chat_message) = ChatMessage::from_raw_message(
                                         priv_msg.message_text,
                                         priv_msg.sender.login,
                                     ) {
-                                        chat_message.insert().await.unwrap();
+                                        chat_message.with_emotes(emotes).insert().await.unwrap();
                                     }
                                 }
                             }
@@ -57,9 +150,18 @@ impl TwitchApi {
         let streamer_channel = env::var("STREAMER").expect("STREAMER env is not set");
         client.join(streamer_channel.to_owned()).unwrap();
 
+        if logged_in {
+            let notifier = client.clone();
+            let channel = streamer_channel.clone();
+            tokio::spawn(async move {
+                Self::send_notifications(notifier, channel, notifier_shutdown).await;
+            });
+        }
+
         // keep the tokio executor alive.
         // If you return instead of waiting the background task will exit.
         join_handle.await?.expect("Error in join handle");
+        client.part(streamer_channel);
         Ok(())
     }
 }
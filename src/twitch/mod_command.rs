@@ -0,0 +1,100 @@
+use crate::event_poller::kill_switch::KillSwitch;
+use crate::event_poller::queue_control::QueueControl;
+use crate::jobs::history::Actor;
+use crate::tts::queue::TtsQueue;
+use crate::workspace::Checkpoint;
+use tracing::info;
+use twitch_irc::message::PrivmsgMessage;
+
+/// Queue levers available to channel moderators and the broadcaster
+#[derive(Debug)]
+pub enum ModCommand {
+    Pause,
+    Resume,
+    Skip,
+    Purge,
+    Bump {
+        id: String,
+        priority: i32,
+    },
+    Requeue {
+        id: String,
+    },
+    Undo {
+        id: String,
+    },
+    Kill,
+    /// Cuts off the announcement being spoken
+    TtsSkip,
+    TtsVolume {
+        volume: i32,
+    },
+}
+
+impl ModCommand {
+    pub fn is_moderator(message: &PrivmsgMessage) -> bool {
+        message
+            .badges
+            .iter()
+            .any(|b| b.name == "moderator" || b.name == "broadcaster")
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut parts = text.split_whitespace();
+        let command = parts.next()?.to_uppercase();
+        let command = match command.as_str() {
+            "!PAUSE" => ModCommand::Pause,
+            "!RESUME" => ModCommand::Resume,
+            "!SKIP" => ModCommand::Skip,
+            "!PURGE" => ModCommand::Purge,
+            "!KILL" => ModCommand::Kill,
+            "!TTSSKIP" => ModCommand::TtsSkip,
+            "!VOLUME" => ModCommand::TtsVolume {
+                volume: parts.next()?.parse().ok()?,
+            },
+            "!BUMP" => ModCommand::Bump {
+                id: parts.next()?.to_string(),
+                priority: parts.next().and_then(|p| p.parse().ok()).unwrap_or(10),
+            },
+            "!REQUEUE" => ModCommand::Requeue {
+                id: parts.next()?.to_string(),
+            },
+            "!UNDO" => ModCommand::Undo {
+                id: parts.next()?.to_string(),
+            },
+            _ => return None,
+        };
+        Some(command)
+    }
+
+    pub async fn execute(&self, moderator: &str) -> anyhow::Result<()> {
+        info!("Executing mod command {:?} from {moderator}", self);
+        let actor = Actor::Moderator(moderator.to_string());
+        match self {
+            ModCommand::Pause => QueueControl::pause().await?,
+            ModCommand::Resume => QueueControl::resume().await?,
+            ModCommand::Skip => {
+                QueueControl::skip_current(&actor).await?;
+            }
+            ModCommand::Purge => {
+                QueueControl::purge(&actor).await?;
+            }
+            ModCommand::Bump { id, priority } => QueueControl::set_priority(id, *priority).await?,
+            ModCommand::Requeue { id } => {
+                QueueControl::requeue(id, &actor).await?;
+            }
+            ModCommand::Undo { id } => {
+                Checkpoint::request_undo(id, &actor).await?;
+            }
+            ModCommand::Kill => {
+                let report = KillSwitch::trigger(&actor).await;
+                info!("Kill switch report {:?}", report);
+            }
+            ModCommand::TtsSkip => {
+                TtsQueue::skip_current().await?;
+            }
+            ModCommand::TtsVolume { volume } => TtsQueue::set_volume(*volume).await?,
+        }
+        Ok(())
+    }
+}
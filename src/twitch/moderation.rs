@@ -0,0 +1,133 @@
+use crate::jobs::history::{Actor, execute_as};
+use crate::pg::pg::PgConnect;
+use crate::twitch::chat_message::MessageStatus;
+use crate::twitch::dedup;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::str::FromStr;
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ModerationFilter {
+    /// Defaults to `UNVERIFIED`
+    pub status: Option<String>,
+    /// Stored command name, e.g. `!STORE` for `!PROMPT`
+    pub command: Option<String>,
+    pub username: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModerationItem {
+    id: Uuid,
+    username: String,
+    command: String,
+    text: String,
+    status: String,
+    rejection_reason: Option<String>,
+    /// Score and flags when the prompt was held by screening
+    screening: Option<Value>,
+    created_at: NaiveDateTime,
+}
+
+/// Review queue for `!PROMPT`/`!REPLY` messages that land as `UNVERIFIED`
+pub struct Moderation {}
+
+impl Moderation {
+    fn parse_ids(ids: &[String]) -> anyhow::Result<Vec<Uuid>> {
+        Ok(ids
+            .iter()
+            .map(|id| Uuid::from_str(id))
+            .collect::<Result<Vec<_>, _>>()?)
+    }
+
+    pub async fn list(filter: &ModerationFilter) -> anyhow::Result<Vec<ModerationItem>> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let status = filter
+            .status
+            .clone()
+            .unwrap_or(MessageStatus::Unverified.to_string());
+        let limit = filter.limit.unwrap_or(100).clamp(1, 500);
+        let query = "SELECT * FROM chat_messages WHERE status = $1 \
+            AND ($2::text IS NULL OR command = $2) \
+            AND ($3::text IS NULL OR username = $3) \
+            AND ($4::timestamptz IS NULL OR created_at >= $4::timestamptz) \
+            AND ($5::timestamptz IS NULL OR created_at <= $5::timestamptz) \
+            ORDER BY created_at asc LIMIT $6";
+        let rows = client
+            .query(
+                query,
+                &[
+                    &status,
+                    &filter.command,
+                    &filter.username,
+                    &filter.from,
+                    &filter.to,
+                    &limit,
+                ],
+            )
+            .await?;
+        rows.iter().map(Self::from_row).collect()
+    }
+
+    pub async fn reject(ids: &[String], reason: &str, actor: &Actor) -> anyhow::Result<u64> {
+        let ids = Self::parse_ids(ids)?;
+        let query = "UPDATE chat_messages SET status = $1, rejection_reason = $2 \
+            WHERE status = $3 AND id = ANY($4)";
+        execute_as(
+            actor,
+            Some(reason),
+            query,
+            &[
+                &MessageStatus::Rejected.to_string(),
+                &reason,
+                &MessageStatus::Unverified.to_string(),
+                &ids,
+            ],
+        )
+        .await
+    }
+
+    /// Replaces the text of an unverified message and approves it in one go.
+    /// Prompts get a new dedup hash, an edit matching a queued prompt fails on the unique index.
+    pub async fn edit_and_approve(id: &str, text: &str, actor: &Actor) -> anyhow::Result<u64> {
+        let id = Uuid::from_str(id)?;
+        let normalized = dedup::normalize(text, &[]);
+        let query = "UPDATE chat_messages SET text = $1, status = $2, screening_override = screening IS NOT NULL, \
+            normalized_text = CASE WHEN normalized_hash IS NULL THEN NULL ELSE $5 END, \
+            normalized_hash = CASE WHEN normalized_hash IS NULL THEN NULL ELSE $6 END \
+            WHERE status = $3 AND id = $4";
+        execute_as(
+            actor,
+            Some("edited and approved"),
+            query,
+            &[
+                &text.trim(),
+                &MessageStatus::Awaiting.to_string(),
+                &MessageStatus::Unverified.to_string(),
+                &id,
+                &normalized,
+                &dedup::hash(&normalized),
+            ],
+        )
+        .await
+    }
+
+    fn from_row(row: &Row) -> anyhow::Result<ModerationItem> {
+        Ok(ModerationItem {
+            id: row.try_get("id")?,
+            username: row.try_get("username")?,
+            command: row.try_get("command")?,
+            text: row.try_get("text")?,
+            status: row.try_get("status")?,
+            rejection_reason: row.try_get("rejection_reason")?,
+            screening: row.try_get("screening")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
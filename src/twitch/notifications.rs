@@ -0,0 +1,33 @@
+use crate::pg::pg::PgConnect;
+
+/// Outbox of messages for viewers, sent by the chat bot when it is logged in
+#[derive(Debug)]
+pub struct ChatNotification {
+    pub id: i64,
+    pub text: String,
+}
+
+impl ChatNotification {
+    pub async fn pending(limit: i64) -> anyhow::Result<Vec<Self>> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "SELECT id, text FROM chat_notifications WHERE sent_at IS NULL ORDER BY created_at asc LIMIT $1";
+        let rows = client.query(query, &[&limit]).await?;
+        rows.iter()
+            .map(|row| {
+                Ok(Self {
+                    id: row.try_get("id")?,
+                    text: row.try_get("text")?,
+                })
+            })
+            .collect()
+    }
+
+    pub async fn mark_sent(&self) -> anyhow::Result<()> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "UPDATE chat_notifications SET sent_at = now() WHERE id = $1";
+        client.execute(query, &[&self.id]).await?;
+        Ok(())
+    }
+}
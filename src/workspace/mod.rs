@@ -0,0 +1,498 @@
+pub mod sandbox;
+
+use crate::jobs::history::{Actor, execute_as};
+use crate::pg::pg::PgConnect;
+use crate::redact::redact;
+use crate::twitch::chat_message::{ChatMessage, MessageCommands, MessageStatus};
+use crate::workspace::sandbox::{SandboxMode, sandbox_dir};
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::str::FromStr;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio_postgres::Row;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Diffs above this size are cut before they are stored
+const MAX_DIFF_BYTES: usize = 512 * 1024;
+
+/// The real project, `AGENT_WORKSPACE`. Checkpoints are off while it is unset.
+pub fn project_dir() -> Option<PathBuf> {
+    env::var("AGENT_WORKSPACE")
+        .ok()
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Directory the agent works in, the sandbox worktree when `AGENT_SANDBOX` is on
+pub fn workspace_dir() -> Option<PathBuf> {
+    match SandboxMode::from_env() {
+        SandboxMode::Off => project_dir(),
+        SandboxMode::Worktree | SandboxMode::Container => sandbox_dir(),
+    }
+}
+
+async fn git(dir: &Path, args: &[&str]) -> anyhow::Result<String> {
+    run_git(dir, args, &[], None).await
+}
+
+async fn run_git(
+    dir: &Path,
+    args: &[&str],
+    envs: &[(&str, &str)],
+    input: Option<&str>,
+) -> anyhow::Result<String> {
+    let mut child = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args([
+            "-c",
+            "user.name=twitch-bot",
+            "-c",
+            "user.email=twitch-bot@localhost",
+        ])
+        .args(args)
+        .envs(envs.iter().copied())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(input.unwrap_or_default().as_bytes())
+            .await?;
+    }
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .trim_end()
+        .to_string())
+}
+
+/// Private ref of a checkpoint, never checked out and invisible to `git log` of the branch
+fn checkpoint_ref(message_id: &str, stage: &str) -> String {
+    format!("refs/twitch-bot/checkpoints/{message_id}/{stage}")
+}
+
+/// Snapshots the workspace into a commit under `ref_name` without touching the branch, the
+/// index or the working tree. Files named in `SANDBOX_DENY_NAMES` are left out of the snapshot.
+async fn snapshot(
+    dir: &Path,
+    parent: Option<&str>,
+    ref_name: &str,
+    message: &str,
+) -> anyhow::Result<String> {
+    let index = git(dir, &["rev-parse", "--git-path", "twitch-bot-index"]).await?;
+    let index = dir.join(index).display().to_string();
+    let envs = [("GIT_INDEX_FILE", index.as_str())];
+    let has_head = git(dir, &["rev-parse", "--verify", "-q", "HEAD"])
+        .await
+        .is_ok();
+    if has_head {
+        run_git(dir, &["read-tree", "HEAD"], &envs, None).await?;
+    } else {
+        run_git(dir, &["read-tree", "--empty"], &envs, None).await?;
+    }
+    let denied = sandbox::denied_names();
+    let mut add = vec![
+        "add".to_string(),
+        "-A".to_string(),
+        "--".to_string(),
+        ".".to_string(),
+    ];
+    let mut remove = vec![
+        "rm".to_string(),
+        "-r".to_string(),
+        "--cached".to_string(),
+        "--ignore-unmatch".to_string(),
+        "--".to_string(),
+    ];
+    for name in &denied {
+        add.push(format!(":(exclude,glob)**/{name}"));
+        add.push(format!(":(exclude,glob)**/{name}/**"));
+        remove.push(format!(":(glob)**/{name}"));
+    }
+    let add: Vec<&str> = add.iter().map(String::as_str).collect();
+    run_git(dir, &add, &envs, None).await?;
+    if !denied.is_empty() {
+        // tracked denied files come in with HEAD
+        let remove: Vec<&str> = remove.iter().map(String::as_str).collect();
+        let removed = run_git(dir, &remove, &envs, None).await?;
+        if !removed.is_empty() {
+            warn!("Left denied files out of the checkpoint: {removed}");
+        }
+    }
+    let tree = run_git(dir, &["write-tree"], &envs, None).await?;
+    let mut commit = vec!["commit-tree", tree.as_str(), "-m", message];
+    if let Some(parent) = parent {
+        commit.extend(["-p", parent]);
+    }
+    let sha = git(dir, &commit).await?;
+    git(dir, &["update-ref", ref_name, &sha]).await?;
+    Ok(sha)
+}
+
+/// Applies the changes between two checkpoints to the working tree, `reverse` takes them back.
+/// Nothing is committed and a patch that doesn't apply leaves the working tree untouched.
+async fn apply_changes(
+    dir: &Path,
+    base: &str,
+    head: &str,
+    reverse: bool,
+) -> anyhow::Result<String> {
+    let patch = git(dir, &["diff", "--binary", base, head]).await?;
+    if !patch.is_empty() {
+        let args: &[&str] = if reverse {
+            &["apply", "-R"]
+        } else {
+            &["apply"]
+        };
+        run_git(dir, args, &[], Some(&format!("{patch}\n"))).await?;
+    }
+    git(dir, &["diff", "--shortstat", base, head]).await
+}
+
+/// Snapshots around a `!PROMPT` job under `refs/twitch-bot/checkpoints/<id>`, the `after` commit
+/// holds exactly what the agent changed. The checked-out branch is never committed to.
+#[derive(Debug, Serialize)]
+pub struct Checkpoint {
+    message_id: Uuid,
+    workspace: String,
+    base_sha: String,
+    head_sha: Option<String>,
+    diff: Option<String>,
+    created_at: DateTime<Utc>,
+    finished_at: Option<DateTime<Utc>>,
+    reverted_at: Option<DateTime<Utc>>,
+    promoted_at: Option<DateTime<Utc>>,
+}
+
+impl Checkpoint {
+    /// Snapshots the workspace as it is before the job runs
+    pub async fn begin(message_id: &str) -> anyhow::Result<()> {
+        let Some(dir) = workspace_dir() else {
+            return Ok(());
+        };
+        let head = git(&dir, &["rev-parse", "--verify", "-q", "HEAD"])
+            .await
+            .ok();
+        let base_sha = snapshot(
+            &dir,
+            head.as_deref(),
+            &checkpoint_ref(message_id, "before"),
+            &format!("checkpoint: before job {message_id}"),
+        )
+        .await?;
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "INSERT INTO job_checkpoints (message_id, workspace, base_sha) VALUES ($1, $2, $3) \
+            ON CONFLICT (message_id) DO UPDATE SET base_sha = $3, head_sha = NULL, diff = NULL, \
+            finished_at = NULL, reverted_at = NULL";
+        let message_id = Uuid::from_str(message_id)?;
+        client
+            .execute(query, &[&message_id, &dir.display().to_string(), &base_sha])
+            .await?;
+        Ok(())
+    }
+
+    /// Snapshots what the agent changed and stores the diff, returns the diff stat for the job result
+    pub async fn finish(message_id: &str) -> anyhow::Result<Option<String>> {
+        let Some(checkpoint) = Self::get(message_id).await? else {
+            return Ok(None);
+        };
+        if checkpoint.head_sha.is_some() {
+            return Ok(None);
+        }
+        let dir = PathBuf::from(&checkpoint.workspace);
+        let head_sha = snapshot(
+            &dir,
+            Some(&checkpoint.base_sha),
+            &checkpoint_ref(message_id, "after"),
+            &format!("checkpoint: after job {message_id}"),
+        )
+        .await?;
+        let range = format!("{}..{head_sha}", checkpoint.base_sha);
+        // diffs are served by the API, a committed secret must not end up there
+        let mut diff = redact(&git(&dir, &["diff", &range]).await?, "checkpoint diff");
+        if diff.len() > MAX_DIFF_BYTES {
+            let mut end = MAX_DIFF_BYTES;
+            while !diff.is_char_boundary(end) {
+                end -= 1;
+            }
+            diff.truncate(end);
+            diff.push_str("\n... diff truncated");
+        }
+        let stat = git(&dir, &["diff", "--shortstat", &range]).await?;
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "UPDATE job_checkpoints SET head_sha = $1, diff = $2, finished_at = now() WHERE message_id = $3";
+        client
+            .execute(query, &[&head_sha, &diff, &checkpoint.message_id])
+            .await?;
+        Ok(Some(stat.trim().to_string()))
+    }
+
+    pub async fn get(message_id: &str) -> anyhow::Result<Option<Self>> {
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "SELECT * FROM job_checkpoints WHERE message_id = $1";
+        let message_id = Uuid::from_str(message_id)?;
+        let row = client.query_opt(query, &[&message_id]).await?;
+        row.as_ref().map(Self::from_row).transpose()
+    }
+
+    /// Queues an `!UNDO` job ahead of everything else, the worker owning the workspace runs it
+    pub async fn request_undo(id_or_prefix: &str, actor: &Actor) -> anyhow::Result<Uuid> {
+        Self::request(MessageCommands::Undo, id_or_prefix, actor, "undo requested").await
+    }
+
+    /// Queues a `!PROMOTE` job that applies reviewed sandbox changes to the real project
+    pub async fn request_promote(id_or_prefix: &str, actor: &Actor) -> anyhow::Result<Uuid> {
+        Self::request(
+            MessageCommands::Promote,
+            id_or_prefix,
+            actor,
+            "promote requested",
+        )
+        .await
+    }
+
+    async fn request(
+        command: MessageCommands,
+        id_or_prefix: &str,
+        actor: &Actor,
+        reason: &str,
+    ) -> anyhow::Result<Uuid> {
+        let id = ChatMessage::resolve_id(id_or_prefix).await?;
+        let query = "INSERT INTO chat_messages (username, text, command, status, priority) \
+            VALUES ($1, $2, $3, $4, 1000)";
+        execute_as(
+            actor,
+            Some(reason),
+            query,
+            &[
+                &actor.to_string(),
+                &id.to_string(),
+                &command.to_string(),
+                &MessageStatus::Awaiting.to_string(),
+            ],
+        )
+        .await?;
+        Ok(id)
+    }
+
+    /// Applies the changes a job made in the sandbox to the working tree of the real project,
+    /// committing them is left to the streamer
+    pub async fn promote(message_id: &str) -> anyhow::Result<String> {
+        if SandboxMode::from_env() == SandboxMode::Off {
+            return Err(anyhow!("Nothing to promote, AGENT_SANDBOX is off"));
+        }
+        let project = project_dir().ok_or(anyhow!("AGENT_WORKSPACE is not set"))?;
+        let checkpoint = Self::get(message_id)
+            .await?
+            .ok_or(anyhow!("No checkpoint for job {message_id}"))?;
+        if checkpoint.promoted_at.is_some() {
+            return Err(anyhow!("Job {message_id} is already promoted"));
+        }
+        if checkpoint.reverted_at.is_some() {
+            return Err(anyhow!("Job {message_id} was reverted in the sandbox"));
+        }
+        let head_sha = checkpoint
+            .head_sha
+            .as_deref()
+            .ok_or(anyhow!("Job {message_id} has not finished"))?;
+        if checkpoint
+            .diff
+            .as_deref()
+            .is_some_and(|diff| diff.is_empty())
+        {
+            return Err(anyhow!("Job {message_id} changed nothing"));
+        }
+        let changes = apply_changes(&project, &checkpoint.base_sha, head_sha, false)
+            .await
+            .inspect_err(|err| warn!("Promoting job {message_id} failed: {err:#}"))?;
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "UPDATE job_checkpoints SET promoted_at = now() WHERE message_id = $1";
+        client.execute(query, &[&checkpoint.message_id]).await?;
+        Ok(changes)
+    }
+
+    /// Takes the changes of a finished job back out of the working tree, later jobs stay in place.
+    /// A conflicting revert leaves the workspace untouched.
+    pub async fn undo(message_id: &str) -> anyhow::Result<String> {
+        let checkpoint = Self::get(message_id)
+            .await?
+            .ok_or(anyhow!("No checkpoint for job {message_id}"))?;
+        if checkpoint.reverted_at.is_some() {
+            return Err(anyhow!("Job {message_id} is already reverted"));
+        }
+        let head_sha = checkpoint
+            .head_sha
+            .as_deref()
+            .ok_or(anyhow!("Job {message_id} has not finished"))?;
+        let dir = PathBuf::from(&checkpoint.workspace);
+        let changes = apply_changes(&dir, &checkpoint.base_sha, head_sha, true)
+            .await
+            .inspect_err(|err| warn!("Reverting job {message_id} failed: {err:#}"))?;
+        if changes.is_empty() {
+            info!("Job {message_id} changed nothing, nothing to undo");
+        }
+        let pool = PgConnect::create_pool_from_env()?;
+        let client = pool.get().await?;
+        let query = "UPDATE job_checkpoints SET reverted_at = now() WHERE message_id = $1";
+        client.execute(query, &[&checkpoint.message_id]).await?;
+        Ok(changes)
+    }
+
+    fn from_row(row: &Row) -> anyhow::Result<Self> {
+        Ok(Self {
+            message_id: row.try_get("message_id")?,
+            workspace: row.try_get("workspace")?,
+            base_sha: row.try_get("base_sha")?,
+            head_sha: row.try_get("head_sha")?,
+            diff: row.try_get("diff")?,
+            created_at: row.try_get("created_at")?,
+            finished_at: row.try_get("finished_at")?,
+            reverted_at: row.try_get("reverted_at")?,
+            promoted_at: row.try_get("promoted_at")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn repo() -> PathBuf {
+        let dir = env::temp_dir().join(format!("checkpoint-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        git(&dir, &["init", "-q"]).await.unwrap();
+        std::fs::write(dir.join("main.rs"), "fn main() {}\n").unwrap();
+        std::fs::write(dir.join(".env"), "OPEN_AI_KEY=tracked\n").unwrap();
+        git(&dir, &["add", "-A"]).await.unwrap();
+        git(&dir, &["commit", "-q", "-m", "init"]).await.unwrap();
+        dir
+    }
+
+    async fn head(dir: &Path) -> String {
+        git(dir, &["rev-parse", "HEAD"]).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn snapshot_leaves_branch_and_index_alone() {
+        let dir = repo().await;
+        let before = head(&dir).await;
+        std::fs::write(dir.join("new.rs"), "// new\n").unwrap();
+
+        let sha = snapshot(
+            &dir,
+            Some(&before),
+            &checkpoint_ref("1", "before"),
+            "before",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(head(&dir).await, before);
+        assert_eq!(
+            git(&dir, &["diff", "--cached", "--name-only"])
+                .await
+                .unwrap(),
+            ""
+        );
+        assert_eq!(
+            git(&dir, &["rev-parse", &checkpoint_ref("1", "before")])
+                .await
+                .unwrap(),
+            sha
+        );
+        let files = git(&dir, &["ls-tree", "-r", "--name-only", &sha])
+            .await
+            .unwrap();
+        assert!(files.contains("new.rs"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn snapshot_skips_denied_files() {
+        let dir = repo().await;
+        std::fs::create_dir_all(dir.join("app")).unwrap();
+        std::fs::write(dir.join("app/.env.local"), "PG_PASS=untracked\n").unwrap();
+
+        let sha = snapshot(&dir, None, &checkpoint_ref("2", "before"), "before")
+            .await
+            .unwrap();
+
+        let files = git(&dir, &["ls-tree", "-r", "--name-only", &sha])
+            .await
+            .unwrap();
+        assert_eq!(files, "main.rs");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn apply_changes_reverts_and_promotes_without_committing() {
+        let dir = repo().await;
+        let before = head(&dir).await;
+        let base = snapshot(
+            &dir,
+            Some(&before),
+            &checkpoint_ref("3", "before"),
+            "before",
+        )
+        .await
+        .unwrap();
+        std::fs::write(dir.join("main.rs"), "fn main() { println!(\"hi\"); }\n").unwrap();
+        let after = snapshot(&dir, Some(&base), &checkpoint_ref("3", "after"), "after")
+            .await
+            .unwrap();
+
+        let changes = apply_changes(&dir, &base, &after, true).await.unwrap();
+        assert!(changes.contains("1 file changed"));
+        assert_eq!(
+            std::fs::read_to_string(dir.join("main.rs")).unwrap(),
+            "fn main() {}\n"
+        );
+
+        apply_changes(&dir, &base, &after, false).await.unwrap();
+        assert!(
+            std::fs::read_to_string(dir.join("main.rs"))
+                .unwrap()
+                .contains("hi")
+        );
+        assert_eq!(head(&dir).await, before);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn conflicting_revert_leaves_workspace_untouched() {
+        let dir = repo().await;
+        let base = snapshot(&dir, None, &checkpoint_ref("4", "before"), "before")
+            .await
+            .unwrap();
+        std::fs::write(dir.join("main.rs"), "fn main() { job(); }\n").unwrap();
+        let after = snapshot(&dir, Some(&base), &checkpoint_ref("4", "after"), "after")
+            .await
+            .unwrap();
+        std::fs::write(dir.join("main.rs"), "fn main() { later(); }\n").unwrap();
+
+        assert!(apply_changes(&dir, &base, &after, true).await.is_err());
+        assert_eq!(
+            std::fs::read_to_string(dir.join("main.rs")).unwrap(),
+            "fn main() { later(); }\n"
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,428 @@
+use crate::workspace::{git, project_dir};
+use anyhow::anyhow;
+use std::env;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+const SANDBOX_BRANCH: &str = "sandbox";
+
+/// How the agent is isolated from the real project, `AGENT_SANDBOX`
+#[derive(Debug, Clone, PartialEq)]
+pub enum SandboxMode {
+    /// The agent works in `AGENT_WORKSPACE` with the environment of the bot
+    Off,
+    /// A git worktree of the project on the `sandbox` branch and a scrubbed environment.
+    /// Not isolated, the agent runs as the bot user and can still read files outside the worktree.
+    Worktree,
+    /// The worktree mounted into a hardened `SANDBOX_IMAGE` container, the only isolated mode.
+    /// `SANDBOX_IMAGE` and `SANDBOX_NETWORK` have to be set.
+    Container,
+}
+
+impl SandboxMode {
+    pub fn from_env() -> Self {
+        match env::var("AGENT_SANDBOX").as_deref() {
+            Ok("worktree") => SandboxMode::Worktree,
+            Ok("container") => SandboxMode::Container,
+            _ => SandboxMode::Off,
+        }
+    }
+}
+
+fn list_env(name: &str, default: &str) -> Vec<String> {
+    env::var(name)
+        .unwrap_or(default.to_string())
+        .split(',')
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect()
+}
+
+/// Untracked paths copied from the project into the sandbox, `SANDBOX_ALLOW_PATHS`
+fn allowed_paths() -> Vec<String> {
+    list_env("SANDBOX_ALLOW_PATHS", "")
+}
+
+/// File names never present in the sandbox, `SANDBOX_DENY_NAMES`
+pub(crate) fn denied_names() -> Vec<String> {
+    list_env("SANDBOX_DENY_NAMES", ".env,.env.local,.env.production")
+}
+
+fn is_denied(path: &Path) -> bool {
+    let denied = denied_names();
+    path.components().any(|c| {
+        let name = c.as_os_str().to_string_lossy();
+        denied.iter().any(|d| *d == name)
+    })
+}
+
+/// Where the sandbox worktree lives, `SANDBOX_DIR` or `<project>-sandbox` next to the project
+pub fn sandbox_dir() -> Option<PathBuf> {
+    if let Ok(dir) = env::var("SANDBOX_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+    let project = project_dir()?;
+    let name = project.file_name()?.to_string_lossy().to_string();
+    Some(project.with_file_name(format!("{name}-sandbox")))
+}
+
+/// Variables the agent process gets, `SANDBOX_ENV_ALLOW`. Everything else, like `OPEN_AI_KEY`
+/// and the Postgres credentials loaded from `.env`, is dropped.
+pub fn agent_env() -> Vec<(String, String)> {
+    list_env(
+        "SANDBOX_ENV_ALLOW",
+        "PATH,HOME,LANG,TERM,GEMINI_API_KEY,GOOGLE_API_KEY",
+    )
+    .into_iter()
+    .filter_map(|name| env::var(&name).ok().map(|value| (name, value)))
+    .collect()
+}
+
+/// Creates the sandbox worktree on first use and syncs the allowlisted paths into it
+pub async fn prepare() -> anyhow::Result<()> {
+    if SandboxMode::from_env() == SandboxMode::Off {
+        return Ok(());
+    }
+    if SandboxMode::from_env() == SandboxMode::Container {
+        // refuse to start rather than fail on the first prompt
+        Container::from_env()?;
+    }
+    let project = project_dir().ok_or(anyhow!("AGENT_SANDBOX needs AGENT_WORKSPACE"))?;
+    let sandbox = sandbox_dir().ok_or(anyhow!("Can't derive the sandbox dir"))?;
+    if !sandbox.exists() {
+        info!("Creating sandbox worktree in {}", sandbox.display());
+        let sandbox_path = sandbox.display().to_string();
+        let branch_exists = git(&project, &["rev-parse", "--verify", "-q", SANDBOX_BRANCH])
+            .await
+            .is_ok();
+        if branch_exists {
+            git(
+                &project,
+                &["worktree", "add", &sandbox_path, SANDBOX_BRANCH],
+            )
+            .await?;
+        } else {
+            git(
+                &project,
+                &["worktree", "add", "-b", SANDBOX_BRANCH, &sandbox_path],
+            )
+            .await?;
+        }
+    }
+    for path in allowed_paths() {
+        let path = PathBuf::from(path);
+        if path.is_absolute() || is_denied(&path) {
+            return Err(anyhow!(
+                "{} can't be allowed in the sandbox",
+                path.display()
+            ));
+        }
+        copy_recursive(&project.join(&path), &sandbox.join(&path))?;
+    }
+    remove_denied(&sandbox)?;
+    Ok(())
+}
+
+fn copy_recursive(from: &Path, to: &Path) -> anyhow::Result<()> {
+    if is_denied(from) {
+        return Ok(());
+    }
+    if from.is_dir() {
+        std::fs::create_dir_all(to)?;
+        for entry in std::fs::read_dir(from)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &to.join(entry.file_name()))?;
+        }
+    } else if from.is_file() {
+        if let Some(parent) = to.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(from, to)?;
+    }
+    Ok(())
+}
+
+/// Deletes denied files that made it into the sandbox, e.g. a committed `.env`
+fn remove_denied(dir: &Path) -> anyhow::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_name() == ".git" {
+            continue;
+        }
+        let denied = denied_names()
+            .iter()
+            .any(|d| *d == entry.file_name().to_string_lossy());
+        if denied {
+            info!("Removing {} from the sandbox", path.display());
+            if path.is_dir() {
+                std::fs::remove_dir_all(&path)?;
+            } else {
+                std::fs::remove_file(&path)?;
+            }
+        } else if path.is_dir() && !path.is_symlink() {
+            remove_denied(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// How the hardened agent container is started
+#[derive(Debug, Clone, PartialEq)]
+struct Container {
+    /// `SANDBOX_IMAGE`, has to ship the agent CLI
+    image: String,
+    /// `SANDBOX_NETWORK`, `none` keeps the agent offline
+    network: String,
+    /// `SANDBOX_USER`
+    user: String,
+    /// The sandbox worktree mounted as `/workspace`
+    workspace: String,
+}
+
+impl Container {
+    /// Errors when the image or network is missing, there is no default that works for every agent
+    fn from_env() -> anyhow::Result<Self> {
+        let image = env::var("SANDBOX_IMAGE").map_err(|_| {
+            anyhow!(
+                "AGENT_SANDBOX=container needs SANDBOX_IMAGE, an image with the agent CLI installed"
+            )
+        })?;
+        let network = env::var("SANDBOX_NETWORK").map_err(|_| {
+            anyhow!(
+                "AGENT_SANDBOX=container needs SANDBOX_NETWORK, e.g. `bridge` for agents calling a hosted model or `none` for local ones"
+            )
+        })?;
+        let workspace = sandbox_dir()
+            .ok_or(anyhow!("Can't derive the sandbox dir"))?
+            .display()
+            .to_string();
+        Ok(Self {
+            image,
+            network,
+            user: container_user(env::var("SANDBOX_USER").ok()),
+            workspace,
+        })
+    }
+}
+
+/// Container user, `SANDBOX_USER` or `1000:1000`. Never root, so a writable mount can't leave
+/// root owned files behind.
+fn container_user(user: Option<String>) -> String {
+    user.filter(|user| {
+        let uid = user.split(':').next().unwrap_or_default();
+        !uid.is_empty() && uid != "0" && uid != "root"
+    })
+    .unwrap_or("1000:1000".to_string())
+}
+
+/// Wraps the agent command for the configured sandbox mode, returns program and arguments.
+/// `tty` is needed for the interactive agent in the tmux pane.
+pub fn wrap_command(
+    program: &str,
+    args: &[String],
+    tty: bool,
+) -> anyhow::Result<(String, Vec<String>)> {
+    let container = match SandboxMode::from_env() {
+        SandboxMode::Container => Some(Container::from_env()?),
+        SandboxMode::Off | SandboxMode::Worktree => None,
+    };
+    Ok(wrap(container.as_ref(), &agent_env(), program, args, tty))
+}
+
+/// The container drops all capabilities, can't gain privileges and has a read-only root with a
+/// scratch `/tmp`
+fn wrap(
+    container: Option<&Container>,
+    env: &[(String, String)],
+    program: &str,
+    args: &[String],
+    tty: bool,
+) -> (String, Vec<String>) {
+    let Some(container) = container else {
+        return (program.to_string(), args.to_vec());
+    };
+    let mut wrapped = vec![
+        "run".to_string(),
+        "--rm".to_string(),
+        if tty { "-it" } else { "-i" }.to_string(),
+        "--user".to_string(),
+        container.user.clone(),
+        "--cap-drop=ALL".to_string(),
+        "--security-opt".to_string(),
+        "no-new-privileges".to_string(),
+        "--network".to_string(),
+        container.network.clone(),
+        "--read-only".to_string(),
+        "--tmpfs".to_string(),
+        "/tmp".to_string(),
+        "-e".to_string(),
+        "HOME=/tmp".to_string(),
+        "-v".to_string(),
+        format!("{}:/workspace", container.workspace),
+        "-w".to_string(),
+        "/workspace".to_string(),
+    ];
+    for (name, _) in env {
+        if name != "PATH" && name != "HOME" {
+            // value is read from the environment of the docker client
+            wrapped.extend(["-e".to_string(), name.clone()]);
+        }
+    }
+    wrapped.push(container.image.clone());
+    wrapped.push(program.to_string());
+    wrapped.extend(args.iter().cloned());
+    ("docker".to_string(), wrapped)
+}
+
+/// Single quotes a value for the shell command tmux starts the agent with
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Shell command for the tmux pane, started with only the allowed variables when sandboxed
+pub fn wrap_shell_command(command: &str) -> anyhow::Result<String> {
+    let container = match SandboxMode::from_env() {
+        SandboxMode::Off => return Ok(command.to_string()),
+        SandboxMode::Worktree => None,
+        SandboxMode::Container => Some(Container::from_env()?),
+    };
+    Ok(shell_command(container.as_ref(), &agent_env(), command))
+}
+
+/// `exec` keeps the agent, or docker, as the pane process the health check looks for
+fn shell_command(container: Option<&Container>, env: &[(String, String)], command: &str) -> String {
+    let mut parts: Vec<String> = command.split_whitespace().map(String::from).collect();
+    if parts.is_empty() {
+        return command.to_string();
+    }
+    let program = parts.remove(0);
+    let (program, args) = wrap(container, env, &program, &parts, true);
+    let env = env
+        .iter()
+        .map(|(name, value)| format!("{name}={}", shell_quote(value)))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let args = args
+        .iter()
+        .map(|arg| shell_quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!("exec env -i {env} {program} {args}")
+}
+
+/// Process the pane runs for `program`, `docker` in container mode
+pub fn wrapped_program(program: &str) -> String {
+    match SandboxMode::from_env() {
+        SandboxMode::Container => "docker".to_string(),
+        SandboxMode::Off | SandboxMode::Worktree => program.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn container() -> Container {
+        Container {
+            image: "agent:latest".to_string(),
+            network: "bridge".to_string(),
+            user: "1000:1000".to_string(),
+            workspace: "/srv/project-sandbox".to_string(),
+        }
+    }
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn wrap_leaves_command_alone_without_container() {
+        let args = vec!["-p".to_string()];
+        assert_eq!(
+            wrap(None, &[], "gemini", &args, false),
+            ("gemini".to_string(), args)
+        );
+    }
+
+    #[test]
+    fn wrap_hardens_the_container() {
+        let vars = env(&[("PATH", "/bin"), ("GEMINI_API_KEY", "secret")]);
+        let (program, args) = wrap(
+            Some(&container()),
+            &vars,
+            "gemini",
+            &["-y".to_string()],
+            true,
+        );
+        assert_eq!(program, "docker");
+        let joined = args.join(" ");
+        for flag in [
+            "--user 1000:1000",
+            "--cap-drop=ALL",
+            "--security-opt no-new-privileges",
+            "--network bridge",
+            "--read-only",
+            "-v /srv/project-sandbox:/workspace",
+            "-it",
+        ] {
+            assert!(joined.contains(flag), "{flag} missing in {joined}");
+        }
+        assert!(joined.ends_with("-e GEMINI_API_KEY agent:latest gemini -y"));
+        assert!(!joined.contains("secret"));
+        assert!(!joined.contains("-e PATH"));
+    }
+
+    #[test]
+    fn container_user_refuses_root() {
+        assert_eq!(container_user(None), "1000:1000");
+        assert_eq!(container_user(Some("0:0".to_string())), "1000:1000");
+        assert_eq!(container_user(Some("root".to_string())), "1000:1000");
+        assert_eq!(container_user(Some("501:20".to_string())), "501:20");
+    }
+
+    #[test]
+    fn shell_quote_escapes_single_quotes() {
+        assert_eq!(shell_quote("plain"), "'plain'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        assert_eq!(shell_quote("$(rm -rf ~)"), "'$(rm -rf ~)'");
+    }
+
+    #[test]
+    fn shell_command_clears_the_environment() {
+        let vars = env(&[("TERM", "xterm"), ("GEMINI_API_KEY", "a'b")]);
+        assert_eq!(
+            shell_command(None, &vars, "gemini --yolo"),
+            r"exec env -i TERM='xterm' GEMINI_API_KEY='a'\''b' gemini '--yolo'"
+        );
+    }
+
+    #[test]
+    fn shell_command_runs_docker_in_container_mode() {
+        let command = shell_command(Some(&container()), &[], "gemini");
+        assert!(command.starts_with("exec env -i  docker 'run' '--rm' '-it'"));
+        assert!(command.ends_with("'agent:latest' 'gemini'"));
+    }
+
+    #[test]
+    fn remove_denied_deletes_nested_env_files() {
+        let dir = env::temp_dir().join(format!("sandbox-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("app/.env.local")).unwrap();
+        std::fs::create_dir_all(dir.join(".git")).unwrap();
+        std::fs::write(dir.join(".env"), "KEY=1").unwrap();
+        std::fs::write(dir.join(".git/.env"), "kept").unwrap();
+        std::fs::write(dir.join("app/main.rs"), "fn main() {}").unwrap();
+        std::fs::write(dir.join("app/.env.local/key"), "KEY=2").unwrap();
+
+        remove_denied(&dir).unwrap();
+
+        assert!(!dir.join(".env").exists());
+        assert!(!dir.join("app/.env.local").exists());
+        assert!(dir.join("app/main.rs").exists());
+        assert!(dir.join(".git/.env").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}